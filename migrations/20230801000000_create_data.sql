@@ -0,0 +1,3 @@
+CREATE TABLE IF NOT EXISTS data (
+    id INTEGER PRIMARY KEY NOT NULL
+);
@@ -0,0 +1,6 @@
+ALTER TABLE data ADD COLUMN name TEXT NOT NULL DEFAULT '';
+ALTER TABLE data ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE data ADD COLUMN payload TEXT NOT NULL DEFAULT 'null';
+ALTER TABLE data ADD COLUMN created_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z';
+ALTER TABLE data ADD COLUMN updated_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z';
+ALTER TABLE data ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
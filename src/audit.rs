@@ -0,0 +1,82 @@
+use std::sync::{Arc, Mutex};
+
+use axum::async_trait;
+use axum::response::Response;
+use http::request::Parts;
+
+use crate::scope::{ScopeOutcome, ScopedFactory};
+
+/// The changes made while handling a single request. They are written to the `audit` log target
+/// as one record once the response is known, and discarded if the request failed.
+pub struct AuditTrail {
+    request_id: Option<String>,
+    events: Mutex<Vec<String>>,
+}
+
+impl AuditTrail {
+    pub fn record(&self, event: impl Into<String>) {
+        self.events.lock().unwrap().push(event.into());
+    }
+
+    fn take_events(&self) -> Vec<String> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+}
+
+pub(crate) struct AuditTrailFactory;
+
+#[async_trait]
+impl ScopedFactory<AuditTrail> for AuditTrailFactory {
+    async fn create(&self, parts: &Parts) -> Result<Arc<AuditTrail>, Response> {
+        let request_id = parts
+            .headers
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        Ok(Arc::new(AuditTrail {
+            request_id,
+            events: Mutex::new(Vec::new()),
+        }))
+    }
+
+    async fn complete(&self, trail: Arc<AuditTrail>, outcome: ScopeOutcome) {
+        let events = trail.take_events();
+        if events.is_empty() {
+            return;
+        }
+
+        let request_id = trail.request_id.as_deref().unwrap_or("-");
+
+        match outcome {
+            ScopeOutcome::Committed => tracing::info!(target: "audit", request_id, ?events, "changes committed"),
+            ScopeOutcome::RolledBack => {
+                tracing::debug!(target: "audit", request_id, ?events, "discarding changes from failed request")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_completion_drains_recorded_events() {
+        let parts = http::Request::builder()
+            .header("x-request-id", "abc-123")
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0;
+
+        let trail = AuditTrailFactory.create(&parts).await.unwrap();
+        assert_eq!(trail.request_id.as_deref(), Some("abc-123"));
+
+        trail.record("created data 1");
+        trail.record("deleted data 2");
+
+        AuditTrailFactory.complete(trail.clone(), ScopeOutcome::Committed).await;
+        assert!(trail.take_events().is_empty());
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::response::{IntoResponse, Response};
+use http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use http::request::Parts;
+use http::{HeaderValue, StatusCode};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::AuthConfig;
+use crate::container::Container;
+use crate::problem::Problem;
+
+/// The identity a request was made with.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct User {
+    pub id: String,
+    /// The permissions granted to this user, e.g. `data:read`.
+    pub scopes: Vec<String>,
+}
+
+impl User {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn with_scopes<I: IntoIterator<Item = S>, S: Into<String>>(mut self, scopes: I) -> Self {
+        self.scopes = scopes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum AuthError {
+    /// The request carried no bearer token.
+    MissingCredentials,
+    /// The token was malformed, expired or not recognised.
+    InvalidCredentials,
+    /// The identity provider could not be reached to check the token.
+    Unavailable,
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::MissingCredentials => f.write_str("the request requires a bearer token"),
+            AuthError::InvalidCredentials => f.write_str("the bearer token is not valid"),
+            AuthError::Unavailable => f.write_str("the identity provider is unavailable"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let (status, challenge) = match self {
+            AuthError::MissingCredentials => (StatusCode::UNAUTHORIZED, "Bearer"),
+            AuthError::InvalidCredentials => (StatusCode::UNAUTHORIZED, r#"Bearer error="invalid_token""#),
+            AuthError::Unavailable => {
+                return Problem::new(StatusCode::SERVICE_UNAVAILABLE)
+                    .with_detail(self.to_string())
+                    .into_response()
+            }
+        };
+
+        let mut response = Problem::new(status).with_detail(self.to_string()).into_response();
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static(challenge));
+
+        response
+    }
+}
+
+/// Turns a bearer token into the [`User`] it identifies.
+#[async_trait]
+pub trait Authenticator {
+    async fn authenticate(&self, token: &str) -> Result<User, AuthError>;
+}
+
+pub type DynAuthenticator = Arc<dyn Authenticator + Send + Sync>;
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    /// Space separated, as in OAuth 2.0.
+    #[serde(default)]
+    scope: String,
+}
+
+/// Accepts JWTs signed with HS256 using a shared secret. The `sub` claim becomes the user id and
+/// the space separated `scope` claim its scopes; `exp` is required.
+pub struct JwtAuthenticator {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl JwtAuthenticator {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            key: DecodingKey::from_secret(secret),
+            validation: Validation::new(Algorithm::HS256),
+        }
+    }
+}
+
+#[async_trait]
+impl Authenticator for JwtAuthenticator {
+    async fn authenticate(&self, token: &str) -> Result<User, AuthError> {
+        let claims = jsonwebtoken::decode::<Claims>(token, &self.key, &self.validation)
+            .map_err(|_| AuthError::InvalidCredentials)?
+            .claims;
+
+        Ok(User::new(claims.sub).with_scopes(claims.scope.split_whitespace()))
+    }
+}
+
+/// Accepts a fixed set of API keys, each belonging to one user. Only digests of the keys are kept
+/// in memory.
+#[derive(Default)]
+pub struct ApiKeyAuthenticator {
+    users: HashMap<[u8; 32], User>,
+}
+
+impl ApiKeyAuthenticator {
+    pub fn with_key(mut self, key: &str, user: User) -> Self {
+        self.users.insert(Self::digest(key), user);
+        self
+    }
+
+    fn digest(key: &str) -> [u8; 32] {
+        Sha256::digest(key.as_bytes()).into()
+    }
+}
+
+#[async_trait]
+impl Authenticator for ApiKeyAuthenticator {
+    async fn authenticate(&self, token: &str) -> Result<User, AuthError> {
+        self.users
+            .get(&Self::digest(token))
+            .cloned()
+            .ok_or(AuthError::InvalidCredentials)
+    }
+}
+
+/// Tries each authenticator in turn, accepting the first user any of them recognises. With none
+/// configured every token is rejected.
+#[derive(Default)]
+pub struct ChainAuthenticator {
+    authenticators: Vec<DynAuthenticator>,
+}
+
+impl ChainAuthenticator {
+    pub fn with(mut self, authenticator: DynAuthenticator) -> Self {
+        self.authenticators.push(authenticator);
+        self
+    }
+}
+
+#[async_trait]
+impl Authenticator for ChainAuthenticator {
+    async fn authenticate(&self, token: &str) -> Result<User, AuthError> {
+        let mut last_err = AuthError::InvalidCredentials;
+
+        for authenticator in &self.authenticators {
+            match authenticator.authenticate(token).await {
+                Ok(user) => return Ok(user),
+                Err(err) => last_err = err,
+            }
+        }
+
+        Err(last_err)
+    }
+}
+
+/// Builds the authenticators enabled by `config`: JWTs when a secret is set, plus any API keys.
+pub fn build_authenticator(config: &AuthConfig) -> DynAuthenticator {
+    let mut chain = ChainAuthenticator::default();
+
+    if !config.jwt_secret.is_empty() {
+        chain = chain.with(Arc::new(JwtAuthenticator::new(config.jwt_secret.as_bytes())));
+    }
+
+    if !config.api_keys.is_empty() {
+        let api_keys = config.api_keys.iter().fold(ApiKeyAuthenticator::default(), |api_keys, entry| {
+            api_keys.with_key(&entry.key, User::new(&entry.user).with_scopes(entry.scopes.iter().cloned()))
+        });
+        chain = chain.with(Arc::new(api_keys));
+    }
+
+    Arc::new(chain)
+}
+
+/// The authenticated user making the request, identified by the `Authorization: Bearer` header
+/// using the [`Authenticator`] registered in the container. Requests without valid credentials
+/// are rejected with 401.
+#[derive(Clone, Debug)]
+pub struct CurrentUser(pub User);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    Arc<Container>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match authenticate(parts, &Arc::<Container>::from_ref(state)).await? {
+            Some(user) => Ok(CurrentUser(user)),
+            None => Err(AuthError::MissingCredentials.into_response()),
+        }
+    }
+}
+
+/// The user identified by the request's bearer token, or `None` if it didn't send one.
+/// Credentials that were sent but aren't valid are rejected rather than treated as anonymous.
+pub(crate) async fn authenticate(parts: &mut Parts, container: &Container) -> Result<Option<User>, Response> {
+    // Authenticate once per request, however many extractors ask
+    if let Some(CurrentUser(user)) = parts.extensions.get::<CurrentUser>() {
+        return Ok(Some(user.clone()));
+    }
+
+    let Some(token) = bearer_token(parts) else {
+        return Ok(None);
+    };
+
+    let authenticator = container
+        .resolve::<dyn Authenticator + Send + Sync>()
+        .map_err(IntoResponse::into_response)?;
+
+    let user = authenticator.authenticate(token).await.map_err(IntoResponse::into_response)?;
+    parts.extensions.insert(CurrentUser(user.clone()));
+
+    Ok(Some(user))
+}
+
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    let value = parts.headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+
+    (scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty()).then(|| token.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    fn sign(claims: serde_json::Value, secret: &[u8]) -> String {
+        jsonwebtoken::encode(&Header::default(), &claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    fn expires() -> u64 {
+        jsonwebtoken::get_current_timestamp() + 60
+    }
+
+    #[tokio::test]
+    async fn test_jwt_authenticator() {
+        let authenticator = JwtAuthenticator::new(b"secret");
+
+        let token = sign(json!({"sub": "alice", "scope": "data:read data:write", "exp": expires()}), b"secret");
+        let user = authenticator.authenticate(&token).await.unwrap();
+        assert_eq!(user, User::new("alice").with_scopes(["data:read", "data:write"]));
+
+        let forged = sign(json!({"sub": "alice", "exp": expires()}), b"other secret");
+        let expired = sign(json!({"sub": "alice", "exp": 1}), b"secret");
+        let no_expiry = sign(json!({"sub": "alice"}), b"secret");
+
+        for token in [forged.as_str(), expired.as_str(), no_expiry.as_str(), "not a jwt"] {
+            assert!(matches!(
+                authenticator.authenticate(token).await,
+                Err(AuthError::InvalidCredentials)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_accepts_api_keys_and_jwts() {
+        let chain = ChainAuthenticator::default()
+            .with(Arc::new(JwtAuthenticator::new(b"secret")))
+            .with(Arc::new(ApiKeyAuthenticator::default().with_key("key-1", User::new("robot"))));
+
+        assert_eq!(chain.authenticate("key-1").await.unwrap().id, "robot");
+
+        let token = sign(json!({"sub": "alice", "exp": expires()}), b"secret");
+        assert_eq!(chain.authenticate(&token).await.unwrap().id, "alice");
+
+        assert!(chain.authenticate("key-2").await.is_err());
+        assert!(ChainAuthenticator::default().authenticate("key-1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_me_requires_a_valid_bearer_token() {
+        let client = TestApp::builder().with_user(User::new("alice").with_scopes(["data:read"])).build();
+
+        let res = client.get("/me").bearer("alice").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: serde_json::Value = res.json().await;
+        assert_eq!(body, json!({"id": "alice", "scopes": ["data:read"]}));
+
+        let res = client.get("/me").send().await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()["www-authenticate"], "Bearer");
+        assert_eq!(res.headers()["content-type"], crate::problem::PROBLEM_JSON);
+
+        let res = client.get("/me").bearer("mallory").send().await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()["www-authenticate"], r#"Bearer error="invalid_token""#);
+
+        let res = client.get("/me").header("authorization", "Basic YWxpY2U6").send().await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+}
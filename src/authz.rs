@@ -0,0 +1,258 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::response::{IntoResponse, Response};
+use http::request::Parts;
+use http::StatusCode;
+
+use crate::auth::{authenticate, AuthError, User};
+use crate::container::Container;
+use crate::problem::Problem;
+
+/// What a route needs to be allowed to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    Read,
+    Write,
+    Admin,
+}
+
+impl Permission {
+    /// The scope that grants this permission.
+    pub fn scope(self) -> &'static str {
+        match self {
+            Permission::Read => "data:read",
+            Permission::Write => "data:write",
+            Permission::Admin => "data:admin",
+        }
+    }
+}
+
+/// Decides which users may do what.
+#[async_trait]
+pub trait AuthorizationPolicy {
+    /// Whether `user`, or an anonymous caller when `None`, may perform an action that needs
+    /// `permission`.
+    async fn allows(&self, user: Option<&User>, permission: Permission) -> bool;
+}
+
+pub type DynAuthorizationPolicy = Arc<dyn AuthorizationPolicy + Send + Sync>;
+
+/// Grants each permission to users holding its scope. `data:admin` grants every permission, and
+/// anonymous callers are granted none.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScopePolicy;
+
+#[async_trait]
+impl AuthorizationPolicy for ScopePolicy {
+    async fn allows(&self, user: Option<&User>, permission: Permission) -> bool {
+        match user {
+            Some(user) => user.has_scope(permission.scope()) || user.has_scope(Permission::Admin.scope()),
+            None => false,
+        }
+    }
+}
+
+/// Allows everything, including anonymous callers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllowAll;
+
+#[async_trait]
+impl AuthorizationPolicy for AllowAll {
+    async fn allows(&self, _user: Option<&User>, _permission: Permission) -> bool {
+        true
+    }
+}
+
+/// Denies everything.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DenyAll;
+
+#[async_trait]
+impl AuthorizationPolicy for DenyAll {
+    async fn allows(&self, _user: Option<&User>, _permission: Permission) -> bool {
+        false
+    }
+}
+
+/// A permission a handler can demand through [`Authorized`].
+pub trait Requirement {
+    const PERMISSION: Permission;
+}
+
+pub struct Read;
+
+impl Requirement for Read {
+    const PERMISSION: Permission = Permission::Read;
+}
+
+pub struct Write;
+
+impl Requirement for Write {
+    const PERMISSION: Permission = Permission::Write;
+}
+
+pub struct Admin;
+
+impl Requirement for Admin {
+    const PERMISSION: Permission = Permission::Admin;
+}
+
+/// Proof that the caller holds the permission `R` according to the [`AuthorizationPolicy`]
+/// registered in the container. Taking it as a handler argument declares what the route requires.
+///
+/// A caller the policy turns away is rejected with 401 if it didn't authenticate, so it knows to
+/// send credentials, and with 403 otherwise.
+pub struct Authorized<R> {
+    /// The caller, `None` if the policy let an anonymous request through.
+    pub user: Option<User>,
+    requirement: PhantomData<fn() -> R>,
+}
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for Authorized<R>
+where
+    Arc<Container>: FromRef<S>,
+    S: Send + Sync,
+    R: Requirement,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let container = Arc::<Container>::from_ref(state);
+        let user = authenticate(parts, &container).await?;
+
+        let policy = container
+            .resolve::<dyn AuthorizationPolicy + Send + Sync>()
+            .map_err(IntoResponse::into_response)?;
+
+        if policy.allows(user.as_ref(), R::PERMISSION).await {
+            return Ok(Authorized {
+                user,
+                requirement: PhantomData,
+            });
+        }
+
+        match user {
+            None => Err(AuthError::MissingCredentials.into_response()),
+            Some(_) => Err(Problem::new(StatusCode::FORBIDDEN)
+                .with_detail(format!("this requires the {} scope", R::PERMISSION.scope()))
+                .into_response()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idempotency::{IDEMPOTENCY_KEY, IDEMPOTENT_REPLAYED};
+    use crate::test_helpers::*;
+
+    use serde_json::json;
+
+    fn secured_app() -> TestClient {
+        TestApp::builder()
+            .with_policy(ScopePolicy)
+            .with_user(User::new("reader").with_scopes(["data:read"]))
+            .with_user(User::new("writer").with_scopes(["data:read", "data:write"]))
+            .with_user(User::new("admin").with_scopes(["data:admin"]))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_scope_policy() {
+        let reader = User::new("reader").with_scopes(["data:read"]);
+        let admin = User::new("admin").with_scopes(["data:admin"]);
+
+        assert!(ScopePolicy.allows(Some(&reader), Permission::Read).await);
+        assert!(!ScopePolicy.allows(Some(&reader), Permission::Write).await);
+        assert!(ScopePolicy.allows(Some(&admin), Permission::Write).await);
+        assert!(!ScopePolicy.allows(None, Permission::Read).await);
+    }
+
+    #[tokio::test]
+    async fn test_routes_require_their_scope() {
+        let client = secured_app();
+
+        let res = client.post("/data").bearer("writer").json(&data_body(1)).send().await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let res = client.get("/data/1").send().await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        for path in ["/data", "/data/1", "/pot/1"] {
+            let res = client.get(path).bearer("reader").send().await;
+            assert_eq!(res.status(), StatusCode::OK, "{path}");
+        }
+
+        let res = client.post("/data/batch").bearer("reader").json(&json!({"ids": [1]})).send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = client.put("/data/1").bearer("reader").json(&json!({"name": "one"})).send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(res.headers()["content-type"], crate::problem::PROBLEM_JSON);
+        let body: serde_json::Value = res.json().await;
+        assert_eq!(body["detail"], "this requires the data:write scope");
+
+        let res = client.delete("/data/1").bearer("writer").send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = client.delete("/data/1").bearer("admin").send().await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_authorization_is_checked_before_the_request_is_parsed() {
+        let client = secured_app();
+
+        let res = client.get("/data/not-a-number").send().await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = client.post("/data").json(&json!({"name": ""})).send().await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = client.put("/data/not-a-number").bearer("reader").json(&json!({})).send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_replays_are_authorized() {
+        let client = secured_app();
+        let post = || client.post("/data").header(IDEMPOTENCY_KEY, "abc").json(&data_body(1));
+
+        let res = post().bearer("writer").send().await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        // Retrying someone else's request doesn't get their response back
+        let res = post().send().await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(res.headers().get(IDEMPOTENT_REPLAYED).is_none());
+
+        let res = post().bearer("reader").send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = post().bearer("writer").send().await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res.headers()[IDEMPOTENT_REPLAYED], "true");
+    }
+
+    #[tokio::test]
+    async fn test_policies_can_be_swapped() {
+        let client = TestApp::builder().with_policy(AllowAll).build();
+        let res = client.get("/data").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let client = TestApp::builder()
+            .with_policy(DenyAll)
+            .with_user(User::new("admin").with_scopes(["data:admin"]))
+            .build();
+        let res = client.get("/data").bearer("admin").send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // Authorization is checked before the body is parsed
+        let res = client.post("/data").bearer("admin").json(&json!({})).send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+}
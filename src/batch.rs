@@ -0,0 +1,175 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::validation::{Fields, Validate};
+use crate::{Data, DataRepoError};
+
+/// The most ids a single batch request may ask for.
+pub const MAX_BATCH_SIZE: usize = 100;
+
+/// The body of `POST /data/batch`. Ids are kept as they were sent so that one that isn't a valid
+/// id is reported in its own result instead of failing the whole batch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchRequest {
+    pub ids: Vec<Value>,
+}
+
+impl Validate for BatchRequest {
+    fn validate(fields: &mut Fields) -> Option<Self> {
+        let ids = fields.required::<Vec<Value>>("ids")?;
+        let ids = fields.ensure("ids", ids, |ids| {
+            if ids.is_empty() || ids.len() > MAX_BATCH_SIZE {
+                Err(format!("must hold 1 to {MAX_BATCH_SIZE} ids"))
+            } else {
+                Ok(())
+            }
+        })?;
+
+        Some(BatchRequest { ids })
+    }
+}
+
+/// The outcome for one id in a batch.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchResult {
+    Found { id: Value, data: Data },
+    NotFound { id: Value },
+    Invalid { id: Value },
+}
+
+/// One result per requested id, in the order they were requested.
+#[derive(Clone, Debug, Serialize)]
+pub struct BatchResponse {
+    pub results: Vec<BatchResult>,
+}
+
+impl BatchResponse {
+    /// Pairs each id in `request` with its outcome. `retrieved` holds the repo's answers for the
+    /// ids that parsed, in order. Errors that say nothing about the id itself, such as the backend
+    /// being unavailable, fail the whole batch.
+    pub(crate) fn new(
+        request: BatchRequest,
+        retrieved: Vec<Result<Data, DataRepoError>>,
+    ) -> Result<Self, DataRepoError> {
+        let requested = request.parsed_ids().len();
+        if retrieved.len() != requested {
+            return Err(DataRepoError::internal(MismatchedBatch {
+                requested,
+                answered: retrieved.len(),
+            }));
+        }
+
+        let mut retrieved = retrieved.into_iter();
+
+        let results = request
+            .ids
+            .into_iter()
+            .map(|id| match parse_id(&id).and_then(|_| retrieved.next()) {
+                None => Ok(BatchResult::Invalid { id }),
+                Some(Ok(data)) => Ok(BatchResult::Found { id, data }),
+                Some(Err(DataRepoError::NotFound)) => Ok(BatchResult::NotFound { id }),
+                Some(Err(DataRepoError::InvalidRequest)) => Ok(BatchResult::Invalid { id }),
+                Some(Err(err)) => Err(err),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { results })
+    }
+}
+
+/// A repo answered a [`retrieve_many`](crate::DataRepo::retrieve_many) call with a different
+/// number of results than it was asked for.
+#[derive(Debug)]
+pub(crate) struct MismatchedBatch {
+    pub(crate) requested: usize,
+    pub(crate) answered: usize,
+}
+
+impl std::fmt::Display for MismatchedBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the data repo was asked for {} records but answered {}",
+            self.requested, self.answered
+        )
+    }
+}
+
+impl std::error::Error for MismatchedBatch {}
+
+impl BatchRequest {
+    /// The requested ids that are valid record ids, in order.
+    pub(crate) fn parsed_ids(&self) -> Vec<usize> {
+        self.ids.iter().filter_map(parse_id).collect()
+    }
+}
+
+fn parse_id(id: &Value) -> Option<usize> {
+    id.as_u64().and_then(|id| usize::try_from(id).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::data;
+
+    use serde_json::json;
+
+    #[test]
+    fn test_results_follow_the_requested_order() {
+        let request = BatchRequest {
+            ids: vec![json!(2), json!("two"), json!(3), json!(-1), json!(1_024)],
+        };
+        assert_eq!(request.parsed_ids(), vec![2, 3, 1_024]);
+
+        let retrieved = vec![Ok(data(2)), Err(DataRepoError::NotFound), Err(DataRepoError::InvalidRequest)];
+        let response = BatchResponse::new(request, retrieved).unwrap();
+
+        let rendered = serde_json::to_value(&response).unwrap();
+        let statuses: Vec<_> = rendered["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| (result["id"].clone(), result["status"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (json!(2), "found"),
+                (json!("two"), "invalid"),
+                (json!(3), "not_found"),
+                (json!(-1), "invalid"),
+                (json!(1_024), "invalid"),
+            ]
+        );
+        assert_eq!(rendered["results"][0]["data"]["name"], "record 2");
+    }
+
+    #[test]
+    fn test_backend_errors_fail_the_batch() {
+        let request = BatchRequest {
+            ids: vec![json!(1), json!(2)],
+        };
+        let retrieved = vec![Ok(data(1)), Err(DataRepoError::Unavailable)];
+
+        assert!(matches!(
+            BatchResponse::new(request, retrieved),
+            Err(DataRepoError::Unavailable)
+        ));
+    }
+
+    #[test]
+    fn test_a_repo_answering_the_wrong_number_of_ids_fails_the_batch() {
+        let request = BatchRequest {
+            ids: vec![json!(1), json!("one"), json!(2)],
+        };
+
+        for retrieved in [vec![Ok(data(1))], vec![Ok(data(1)), Ok(data(2)), Ok(data(3))]] {
+            assert!(matches!(
+                BatchResponse::new(request.clone(), retrieved),
+                Err(DataRepoError::Internal(_))
+            ));
+        }
+    }
+}
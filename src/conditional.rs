@@ -0,0 +1,178 @@
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::header::{ETAG, IF_MATCH, IF_NONE_MATCH};
+use http::request::Parts;
+use http::{HeaderValue, StatusCode};
+
+use time::OffsetDateTime;
+
+use crate::problem::Problem;
+use crate::{Data, Revision};
+
+/// The strong entity tag of a record, derived from its [`Revision`] as
+/// `"<version>-<creation time in unix nanoseconds>"`. Every update moves a record to a new
+/// version and recreating it resets the creation time, so the tag changes whenever its
+/// representation does.
+pub fn entity_tag(data: &Data) -> String {
+    let revision = data.revision();
+    format!("\"{}-{}\"", revision.version, revision.created_at.unix_timestamp_nanos())
+}
+
+/// Reads the revision back out of a tag produced by [`entity_tag`]. Weak tags never match an
+/// `If-Match` precondition, so they are treated like any other tag we didn't issue.
+fn parse_entity_tag(tag: &str) -> Option<Revision> {
+    let (version, created_at) = tag.strip_prefix('"')?.strip_suffix('"')?.split_once('-')?;
+
+    Some(Revision {
+        version: version.parse().ok()?,
+        created_at: OffsetDateTime::from_unix_timestamp_nanos(created_at.parse().ok()?).ok()?,
+    })
+}
+
+fn header_str<'a>(parts: &'a Parts, name: http::header::HeaderName) -> Result<Option<&'a str>, Problem> {
+    parts
+        .headers
+        .get(&name)
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|_| Problem::new(StatusCode::BAD_REQUEST).with_detail(format!("{name} is not valid ASCII")))
+        })
+        .transpose()
+}
+
+/// The revision an update expects to replace, taken from the `If-Match` header. `None` when the
+/// header is absent or `*`, in which case any existing version may be replaced.
+///
+/// Only a single tag issued by this server can be checked atomically, so anything else is
+/// rejected up front with 412 as it can never match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IfMatch(pub Option<Revision>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = Problem;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match header_str(parts, IF_MATCH)?.map(str::trim) {
+            None | Some("*") => Ok(IfMatch(None)),
+            Some(tag) => parse_entity_tag(tag).map(|revision| IfMatch(Some(revision))).ok_or_else(|| {
+                Problem::new(StatusCode::PRECONDITION_FAILED)
+                    .with_detail("If-Match must be * or a single ETag returned by this server")
+            }),
+        }
+    }
+}
+
+/// The entity tags a client already holds, taken from the `If-None-Match` header.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IfNoneMatch(Option<String>);
+
+impl IfNoneMatch {
+    /// Whether the client's copy of `data` is current. Uses the weak comparison RFC 9110 asks
+    /// for, so `W/"3-…"` matches `"3-…"`.
+    pub fn matches(&self, data: &Data) -> bool {
+        let Some(header) = &self.0 else {
+            return false;
+        };
+
+        let current = entity_tag(data);
+        header.split(',').map(str::trim).any(|tag| {
+            let tag = tag.strip_prefix("W/").unwrap_or(tag);
+            tag == "*" || tag == current
+        })
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfNoneMatch {
+    type Rejection = Problem;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(IfNoneMatch(header_str(parts, IF_NONE_MATCH)?.map(str::to_string)))
+    }
+}
+
+/// A record rendered as JSON with its `ETag`.
+pub struct Tagged(pub Data);
+
+impl IntoResponse for Tagged {
+    fn into_response(self) -> Response {
+        let etag = entity_tag(&self.0);
+        let mut response = Json(self.0).into_response();
+        insert_etag(&mut response, etag);
+        response
+    }
+}
+
+/// The body-less 304 answer to a GET whose `If-None-Match` matched `data`.
+pub fn not_modified(data: &Data) -> Response {
+    let mut response = StatusCode::NOT_MODIFIED.into_response();
+    insert_etag(&mut response, entity_tag(data));
+    response
+}
+
+fn insert_etag(response: &mut Response, etag: String) {
+    let value = HeaderValue::try_from(etag).expect("entity tags to be valid header values");
+    response.headers_mut().insert(ETAG, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::data;
+
+    use http::Request;
+
+    async fn if_match(value: &str) -> Result<IfMatch, Problem> {
+        let (mut parts, _) = Request::builder().header(IF_MATCH, value).body(()).unwrap().into_parts();
+        IfMatch::from_request_parts(&mut parts, &()).await
+    }
+
+    #[tokio::test]
+    async fn test_if_match_parses_a_single_strong_tag() {
+        let mut record = data(1);
+        record.version = 3;
+        let tag = entity_tag(&record);
+
+        assert_eq!(if_match(&tag).await.unwrap(), IfMatch(Some(record.revision())));
+        assert_eq!(if_match("*").await.unwrap(), IfMatch(None));
+
+        let weak = format!("W/{tag}");
+        let several = format!("{tag}, {tag}");
+        for value in [weak.as_str(), several.as_str(), "\"3\"", "3-1", "\"three-1\""] {
+            assert!(if_match(value).await.is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn test_if_none_match_uses_weak_comparison() {
+        let mut record = data(1);
+        record.version = 3;
+        let tag = entity_tag(&record);
+
+        let header = |value: &str| IfNoneMatch(Some(value.to_string()));
+
+        assert!(header(&tag).matches(&record));
+        assert!(header(&format!("W/{tag}")).matches(&record));
+        assert!(header(&format!("\"1-0\", {tag}")).matches(&record));
+        assert!(header("*").matches(&record));
+        assert!(!header("\"3\"").matches(&record));
+        assert!(!IfNoneMatch::default().matches(&record));
+    }
+
+    #[test]
+    fn test_recreated_records_get_a_new_tag() {
+        let original = data(1);
+        let recreated = Data {
+            created_at: original.created_at + time::Duration::milliseconds(1),
+            ..original.clone()
+        };
+
+        assert_eq!(original.version, recreated.version);
+        assert_ne!(entity_tag(&original), entity_tag(&recreated));
+        assert!(!IfNoneMatch(Some(entity_tag(&original))).matches(&recreated));
+    }
+}
@@ -0,0 +1,678 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::Parser;
+use serde::{Deserialize, Deserializer};
+use tracing::Level;
+
+const ENV_PREFIX: &str = "APP_";
+
+/// Runtime configuration for the server. Values are layered, with each source overriding the
+/// previous one: built-in defaults, an optional TOML file, `APP_*` environment variables and
+/// finally command-line flags.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub auth: AuthConfig,
+    pub bind_addr: SocketAddr,
+    pub http: HttpConfig,
+    pub log: LogConfig,
+    pub repo: RepoConfig,
+    pub timeouts: TimeoutConfig,
+}
+
+impl Config {
+    /// Loads the configuration from the process arguments and environment.
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_from(CliArgs::parse(), std::env::vars())
+    }
+
+    pub(crate) fn load_from(
+        args: CliArgs,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigError> {
+        let env: HashMap<String, String> = env
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(ENV_PREFIX))
+            .collect();
+
+        let path = args
+            .config
+            .clone()
+            .or_else(|| env.get("APP_CONFIG").map(PathBuf::from));
+
+        let mut config = match path {
+            Some(path) => Self::from_file(&path)?,
+            None => Self::default(),
+        };
+
+        config.apply_env(&env)?;
+        config.apply_args(args);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        toml::from_str(&contents).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn apply_env(&mut self, env: &HashMap<String, String>) -> Result<(), ConfigError> {
+        env_override(env, "APP_AUTH_JWT_SECRET", &mut self.auth.jwt_secret)?;
+        env_override(env, "APP_BIND_ADDR", &mut self.bind_addr)?;
+        env_override(env, "APP_HTTP_BODY_LIMIT_BYTES", &mut self.http.body_limit_bytes)?;
+        env_override(env, "APP_HTTP_CATCH_PANICS", &mut self.http.catch_panics)?;
+        env_override(env, "APP_HTTP_COMPRESSION", &mut self.http.compression)?;
+        env_override(env, "APP_HTTP_CONCURRENCY_LIMIT", &mut self.http.concurrency_limit)?;
+        env_override(env, "APP_HTTP_REQUEST_ID", &mut self.http.request_id)?;
+        env_override(env, "APP_HTTP_TRACE", &mut self.http.trace)?;
+        env_override(env, "APP_LOG_LEVEL", &mut self.log.level)?;
+        env_override(env, "APP_LOG_FORMAT", &mut self.log.format)?;
+        env_override(env, "APP_REPO_BACKEND", &mut self.repo.backend)?;
+        env_override(env, "APP_REPO_SQLITE_URL", &mut self.repo.sqlite_url)?;
+        env_override(env, "APP_REPO_COALESCE_RETRIEVES", &mut self.repo.coalesce_retrieves)?;
+        env_override(env, "APP_REPO_CACHE_ENABLED", &mut self.repo.cache.enabled)?;
+        env_override(env, "APP_REPO_CACHE_CAPACITY", &mut self.repo.cache.capacity)?;
+        env_override(env, "APP_REPO_CACHE_TTL_SECS", &mut self.repo.cache.ttl_secs)?;
+        env_override(env, "APP_REPO_CACHE_NEGATIVE_TTL_SECS", &mut self.repo.cache.negative_ttl_secs)?;
+        env_override(env, "APP_REPO_RESILIENCE_ENABLED", &mut self.repo.resilience.enabled)?;
+        env_override(env, "APP_REPO_RESILIENCE_CALL_TIMEOUT_MS", &mut self.repo.resilience.call_timeout_ms)?;
+        env_override(env, "APP_REPO_RESILIENCE_MAX_RETRIES", &mut self.repo.resilience.max_retries)?;
+        env_override(env, "APP_REPO_RESILIENCE_BACKOFF_BASE_MS", &mut self.repo.resilience.backoff_base_ms)?;
+        env_override(env, "APP_REPO_RESILIENCE_BACKOFF_MAX_MS", &mut self.repo.resilience.backoff_max_ms)?;
+        env_override(
+            env,
+            "APP_REPO_RESILIENCE_BREAKER_FAILURE_THRESHOLD",
+            &mut self.repo.resilience.breaker_failure_threshold,
+        )?;
+        env_override(env, "APP_REPO_RESILIENCE_BREAKER_OPEN_SECS", &mut self.repo.resilience.breaker_open_secs)?;
+        env_override(env, "APP_TIMEOUTS_REQUEST_SECS", &mut self.timeouts.request_secs)?;
+        env_override(env, "APP_TIMEOUTS_SHUTDOWN_SECS", &mut self.timeouts.shutdown_secs)?;
+
+        Ok(())
+    }
+
+    fn apply_args(&mut self, args: CliArgs) {
+        if let Some(bind_addr) = args.bind_addr {
+            self.bind_addr = bind_addr;
+        }
+
+        if let Some(body_limit_bytes) = args.body_limit_bytes {
+            self.http.body_limit_bytes = body_limit_bytes;
+        }
+
+        if let Some(concurrency_limit) = args.concurrency_limit {
+            self.http.concurrency_limit = concurrency_limit;
+        }
+
+        if let Some(level) = args.log_level {
+            self.log.level = level;
+        }
+
+        if let Some(format) = args.log_format {
+            self.log.format = format;
+        }
+
+        if let Some(backend) = args.repo_backend {
+            self.repo.backend = backend;
+        }
+
+        if let Some(sqlite_url) = args.sqlite_url {
+            self.repo.sqlite_url = sqlite_url;
+        }
+
+        if let Some(request_secs) = args.request_timeout_secs {
+            self.timeouts.request_secs = request_secs;
+        }
+
+        if let Some(shutdown_secs) = args.shutdown_timeout_secs {
+            self.timeouts.shutdown_secs = shutdown_secs;
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.http.body_limit_bytes == 0 {
+            return Err(ConfigError::Invalid("http.body_limit_bytes must be greater than zero".into()));
+        }
+
+        if self.http.concurrency_limit == 0 {
+            return Err(ConfigError::Invalid("http.concurrency_limit must be greater than zero".into()));
+        }
+
+        if self.timeouts.request_secs == 0 {
+            return Err(ConfigError::Invalid("timeouts.request_secs must be greater than zero".into()));
+        }
+
+        if self.repo.cache.enabled && self.repo.cache.capacity == 0 {
+            return Err(ConfigError::Invalid("repo.cache.capacity must be greater than zero".into()));
+        }
+
+        let resilience = &self.repo.resilience;
+        if resilience.enabled && (resilience.call_timeout_ms == 0 || resilience.breaker_failure_threshold == 0) {
+            return Err(ConfigError::Invalid(
+                "repo.resilience.call_timeout_ms and breaker_failure_threshold must be greater than zero".into(),
+            ));
+        }
+
+        if self.repo.backend == RepoBackend::Sqlite && !self.repo.sqlite_url.starts_with("sqlite:") {
+            return Err(ConfigError::Invalid(format!(
+                "repo.sqlite_url must be a sqlite: URL, got {:?}",
+                self.repo.sqlite_url
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            auth: AuthConfig::default(),
+            bind_addr: "[::]:3000".parse().expect("the syntax to be valid"),
+            http: HttpConfig::default(),
+            log: LogConfig::default(),
+            repo: RepoConfig::default(),
+            timeouts: TimeoutConfig::default(),
+        }
+    }
+}
+
+/// How bearer tokens are checked. With neither a JWT secret nor API keys every token is rejected.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Shared secret for HS256-signed JWTs, leave empty to disable them.
+    pub jwt_secret: String,
+    pub api_keys: Vec<ApiKeyConfig>,
+}
+
+// Secrets are kept out of the logs
+impl std::fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("jwt_secret", &if self.jwt_secret.is_empty() { "" } else { "<redacted>" })
+            .field("api_keys", &self.api_keys)
+            .finish()
+    }
+}
+
+/// A static API key and the user it authenticates as.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    pub key: String,
+    pub user: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+impl std::fmt::Debug for ApiKeyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiKeyConfig")
+            .field("key", &"<redacted>")
+            .field("user", &self.user)
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+/// Toggles and limits for the middleware wrapped around every route.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub body_limit_bytes: usize,
+    pub catch_panics: bool,
+    pub compression: bool,
+    /// Maximum number of requests processed at once across all routes, others wait their turn.
+    pub concurrency_limit: usize,
+    /// Generate an `x-request-id` for requests without one and echo it on the response.
+    pub request_id: bool,
+    pub trace: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            body_limit_bytes: 2 * 1024 * 1024,
+            catch_panics: true,
+            compression: true,
+            concurrency_limit: 1_024,
+            request_id: true,
+            trace: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    #[serde(deserialize_with = "deserialize_from_str")]
+    pub level: Level,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: Level::INFO,
+            format: LogFormat::Compact,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Compact,
+    Pretty,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compact" => Ok(LogFormat::Compact),
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {s:?}, expected compact, pretty or json")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RepoConfig {
+    pub backend: RepoBackend,
+    pub sqlite_url: String,
+    /// Share a single backend call between concurrent retrieves of the same record.
+    pub coalesce_retrieves: bool,
+    pub cache: CacheConfig,
+    pub resilience: ResilienceConfig,
+}
+
+impl Default for RepoConfig {
+    fn default() -> Self {
+        Self {
+            backend: RepoBackend::Memory,
+            sqlite_url: "sqlite://data.db".to_string(),
+            coalesce_retrieves: false,
+            cache: CacheConfig::default(),
+            resilience: ResilienceConfig::default(),
+        }
+    }
+}
+
+/// Settings for the record cache placed in front of the repo backend.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Maximum number of records held, the least recently used are evicted first.
+    pub capacity: usize,
+    pub ttl_secs: u64,
+    /// How long a record the backend reported as missing is remembered.
+    pub negative_ttl_secs: u64,
+}
+
+impl CacheConfig {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs)
+    }
+
+    pub fn negative_ttl(&self) -> Duration {
+        Duration::from_secs(self.negative_ttl_secs)
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 1_024,
+            ttl_secs: 60,
+            negative_ttl_secs: 5,
+        }
+    }
+}
+
+/// Timeouts, retries and circuit breaking applied to every call made to the repo backend.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResilienceConfig {
+    pub enabled: bool,
+    pub call_timeout_ms: u64,
+    /// Extra attempts made for a read that failed with a retryable error.
+    pub max_retries: u32,
+    pub backoff_base_ms: u64,
+    pub backoff_max_ms: u64,
+    /// Consecutive backend failures after which calls fail fast.
+    pub breaker_failure_threshold: u32,
+    /// How long calls fail fast before a probe call is let through.
+    pub breaker_open_secs: u64,
+}
+
+impl ResilienceConfig {
+    pub fn call_timeout(&self) -> Duration {
+        Duration::from_millis(self.call_timeout_ms)
+    }
+
+    pub fn backoff_base(&self) -> Duration {
+        Duration::from_millis(self.backoff_base_ms)
+    }
+
+    pub fn backoff_max(&self) -> Duration {
+        Duration::from_millis(self.backoff_max_ms)
+    }
+
+    pub fn breaker_open(&self) -> Duration {
+        Duration::from_secs(self.breaker_open_secs)
+    }
+}
+
+impl Default for ResilienceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            call_timeout_ms: 2_000,
+            max_retries: 2,
+            backoff_base_ms: 50,
+            backoff_max_ms: 1_000,
+            breaker_failure_threshold: 5,
+            breaker_open_secs: 30,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RepoBackend {
+    Memory,
+    Prod,
+    Sqlite,
+}
+
+impl FromStr for RepoBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(RepoBackend::Memory),
+            "prod" => Ok(RepoBackend::Prod),
+            "sqlite" => Ok(RepoBackend::Sqlite),
+            _ => Err(format!("unknown repo backend {s:?}, expected memory, prod or sqlite")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutConfig {
+    pub request_secs: u64,
+    /// How long in-flight requests are given to finish once a shutdown signal is received.
+    pub shutdown_secs: u64,
+}
+
+impl TimeoutConfig {
+    pub fn request(&self) -> Duration {
+        Duration::from_secs(self.request_secs)
+    }
+
+    pub fn shutdown(&self) -> Duration {
+        Duration::from_secs(self.shutdown_secs)
+    }
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            request_secs: 30,
+            shutdown_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Default, Parser)]
+#[command(version, about)]
+pub(crate) struct CliArgs {
+    /// Path to a TOML configuration file, also read from APP_CONFIG
+    #[arg(short, long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Address the HTTP server listens on
+    #[arg(long, value_name = "ADDR")]
+    bind_addr: Option<SocketAddr>,
+
+    /// Largest request body accepted, in bytes
+    #[arg(long, value_name = "BYTES")]
+    body_limit_bytes: Option<usize>,
+
+    /// Maximum number of requests handled concurrently
+    #[arg(long, value_name = "COUNT")]
+    concurrency_limit: Option<usize>,
+
+    /// Default log level (trace, debug, info, warn or error)
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<Level>,
+
+    /// Log output format (compact, pretty or json)
+    #[arg(long, value_name = "FORMAT")]
+    log_format: Option<LogFormat>,
+
+    /// Storage backend for data records (memory, prod or sqlite)
+    #[arg(long, value_name = "BACKEND")]
+    repo_backend: Option<RepoBackend>,
+
+    /// Connection URL used by the sqlite backend
+    #[arg(long, value_name = "URL")]
+    sqlite_url: Option<String>,
+
+    /// Maximum time a single request may take before it is aborted
+    #[arg(long, value_name = "SECS")]
+    request_timeout_secs: Option<u64>,
+
+    /// Time allowed for in-flight requests to drain during shutdown
+    #[arg(long, value_name = "SECS")]
+    shutdown_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, source: toml::de::Error },
+    Env { key: String, value: String, reason: String },
+    Invalid(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => write!(f, "unable to read {}: {source}", path.display()),
+            ConfigError::Parse { path, source } => write!(f, "unable to parse {}: {source}", path.display()),
+            ConfigError::Env { key, value, reason } => write!(f, "invalid value {value:?} for {key}: {reason}"),
+            ConfigError::Invalid(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+fn env_override<T>(env: &HashMap<String, String>, key: &str, target: &mut T) -> Result<(), ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(value) = env.get(key) {
+        *target = value.parse().map_err(|err: T::Err| ConfigError::Env {
+            key: key.to_string(),
+            value: value.clone(),
+            reason: err.to_string(),
+        })?;
+    }
+
+    Ok(())
+}
+
+fn deserialize_from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn args(flags: &[&str]) -> CliArgs {
+        CliArgs::try_parse_from(std::iter::once("axum-testing").chain(flags.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = Config::load_from(CliArgs::default(), env(&[])).unwrap();
+
+        assert_eq!(config.bind_addr, "[::]:3000".parse().unwrap());
+        assert_eq!(config.http.body_limit_bytes, 2 * 1024 * 1024);
+        assert!(config.http.compression);
+        assert_eq!(config.log.level, Level::INFO);
+        assert_eq!(config.log.format, LogFormat::Compact);
+        assert_eq!(config.repo.backend, RepoBackend::Memory);
+        assert_eq!(config.timeouts.request(), Duration::from_secs(30));
+        assert_eq!(config.timeouts.shutdown(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_layering_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+                bind_addr = "127.0.0.1:8000"
+
+                [auth]
+                jwt_secret = "from-file"
+                api_keys = [{ key = "key-1", user = "robot", scopes = ["data:read"] }]
+
+                [log]
+                level = "debug"
+                format = "json"
+
+                [repo]
+                backend = "sqlite"
+                sqlite_url = "sqlite://from-file.db"
+
+                [repo.cache]
+                enabled = true
+                ttl_secs = 10
+            "#,
+        )
+        .unwrap();
+
+        let config = Config::load_from(
+            args(&["--sqlite-url", "sqlite://from-cli.db"]),
+            env(&[
+                ("APP_CONFIG", path.to_str().unwrap()),
+                ("APP_AUTH_JWT_SECRET", "from-env"),
+                ("APP_LOG_LEVEL", "warn"),
+                ("APP_REPO_SQLITE_URL", "sqlite://from-env.db"),
+                ("APP_REPO_CACHE_TTL_SECS", "20"),
+                ("UNRELATED", "ignored"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.bind_addr, "127.0.0.1:8000".parse().unwrap());
+        assert_eq!(config.auth.jwt_secret, "from-env");
+        assert_eq!(config.auth.api_keys[0].user, "robot");
+        assert!(!format!("{config:?}").contains("from-env"));
+        assert!(!format!("{config:?}").contains("key-1"));
+        assert_eq!(config.log.level, Level::WARN);
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert_eq!(config.repo.backend, RepoBackend::Sqlite);
+        assert_eq!(config.repo.sqlite_url, "sqlite://from-cli.db");
+        assert!(config.repo.cache.enabled);
+        assert_eq!(config.repo.cache.ttl(), Duration::from_secs(20));
+        assert_eq!(config.repo.cache.capacity, 1_024);
+    }
+
+    #[test]
+    fn test_invalid_env_value() {
+        let err = Config::load_from(CliArgs::default(), env(&[("APP_REPO_BACKEND", "postgres")])).unwrap_err();
+
+        assert!(matches!(err, ConfigError::Env { ref key, .. } if key == "APP_REPO_BACKEND"));
+    }
+
+    #[test]
+    fn test_unknown_file_keys_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "bind_adr = \"127.0.0.1:8000\"\n").unwrap();
+
+        let err = Config::load_from(args(&["--config", path.to_str().unwrap()]), env(&[])).unwrap_err();
+        assert!(matches!(err, ConfigError::Parse { .. }));
+    }
+
+    #[test]
+    fn test_validation() {
+        let err = Config::load_from(args(&["--request-timeout-secs", "0"]), env(&[])).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)));
+
+        let err = Config::load_from(args(&["--concurrency-limit", "0"]), env(&[])).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)));
+
+        let err = Config::load_from(
+            CliArgs::default(),
+            env(&[("APP_REPO_CACHE_ENABLED", "true"), ("APP_REPO_CACHE_CAPACITY", "0")]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)));
+
+        let err = Config::load_from(
+            CliArgs::default(),
+            env(&[("APP_REPO_RESILIENCE_ENABLED", "true"), ("APP_REPO_RESILIENCE_CALL_TIMEOUT_MS", "0")]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)));
+
+        let err = Config::load_from(CliArgs::default(), env(&[("APP_HTTP_COMPRESSION", "yes")])).unwrap_err();
+        assert!(matches!(err, ConfigError::Env { .. }));
+
+        let err = Config::load_from(
+            args(&["--repo-backend", "sqlite", "--sqlite-url", "data.db"]),
+            env(&[]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)));
+    }
+}
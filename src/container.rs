@@ -0,0 +1,296 @@
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
+use std::future::Future;
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::response::{IntoResponse, Response};
+use futures::future::{BoxFuture, FutureExt};
+use http::request::Parts;
+use http::StatusCode;
+
+use crate::health::{self, HealthStatus};
+use crate::problem::Problem;
+use crate::scope::ScopedFactory;
+
+/// A type map of shared services. Services are registered and resolved by the type they are
+/// exposed as, which is usually a trait object such as `dyn DataRepo + Send + Sync`, so callers
+/// never need to know the concrete implementation behind it.
+#[derive(Clone, Default)]
+pub struct Container {
+    services: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+    health_checks: BTreeMap<&'static str, HealthCheck>,
+}
+
+type HealthCheck = Arc<dyn Fn(&Container) -> BoxFuture<'static, HealthStatus> + Send + Sync>;
+
+impl Container {
+    /// Registers `service` as the implementation of `T`, replacing any previous registration.
+    pub fn register<T>(&mut self, service: Arc<T>) -> &mut Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+    {
+        self.services.insert(Dependency::of::<T>().type_id, Arc::new(service));
+        self
+    }
+
+    /// Registers `factory` to create a fresh `T` for each request that extracts
+    /// [`Scoped<T>`](crate::scope::Scoped), replacing any previous registration.
+    pub fn register_scoped<T, F>(&mut self, factory: F) -> &mut Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+        F: ScopedFactory<T> + 'static,
+    {
+        let factory: Arc<dyn ScopedFactory<T>> = Arc::new(factory);
+        self.register(factory)
+    }
+
+    pub fn resolve<T>(&self) -> Result<Arc<T>, MissingDependency>
+    where
+        T: ?Sized + Send + Sync + 'static,
+    {
+        self.services
+            .get(&Dependency::of::<T>().type_id)
+            .and_then(|service| service.downcast_ref::<Arc<T>>())
+            .cloned()
+            .ok_or(MissingDependency(Dependency::of::<T>()))
+    }
+
+    /// Replaces the registered `T` with the result of wrapping it in `decorate`, so a decorator
+    /// can be layered over whichever implementation was registered without knowing what it is.
+    pub fn decorate<T>(&mut self, decorate: impl FnOnce(Arc<T>) -> Arc<T>) -> Result<&mut Self, MissingDependency>
+    where
+        T: ?Sized + Send + Sync + 'static,
+    {
+        let inner = self.resolve::<T>()?;
+        Ok(self.register(decorate(inner)))
+    }
+
+    /// Registers `check` to report the health of the registered `T` under `name`, replacing any
+    /// previous check with that name. The check is handed whichever `T` is registered when it
+    /// runs, so a service replaced or decorated afterwards is the one that gets checked.
+    pub fn register_health_check<T, F, Fut>(&mut self, name: &'static str, check: F) -> &mut Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+        F: Fn(Arc<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HealthStatus> + Send + 'static,
+    {
+        let check: HealthCheck = Arc::new(move |container: &Container| match container.resolve::<T>() {
+            Ok(service) => check(service).boxed(),
+            Err(err) => futures::future::ready(HealthStatus::unhealthy(err.to_string())).boxed(),
+        });
+        self.health_checks.insert(name, check);
+        self
+    }
+
+    /// Runs every registered health check concurrently, keyed by the name it was registered under.
+    pub async fn health_checks(&self) -> BTreeMap<&'static str, HealthStatus> {
+        let checks = self
+            .health_checks
+            .iter()
+            .map(|(name, check)| async move { (*name, health::check(check(self)).await) });
+
+        futures::future::join_all(checks).await.into_iter().collect()
+    }
+
+    /// Confirms every one of `dependencies` has been registered, reporting the first that hasn't.
+    pub fn verify(&self, dependencies: &[Dependency]) -> Result<(), MissingDependency> {
+        match dependencies
+            .iter()
+            .find(|dependency| !self.services.contains_key(&dependency.type_id))
+        {
+            Some(dependency) => Err(MissingDependency(*dependency)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Identifies a service type that can be registered in a [`Container`].
+#[derive(Clone, Copy, Debug)]
+pub struct Dependency {
+    type_id: TypeId,
+    type_name: &'static str,
+}
+
+impl Dependency {
+    pub fn of<T: ?Sized + 'static>() -> Self {
+        Self {
+            // Keyed on the Arc so unsized trait objects get a usable TypeId
+            type_id: TypeId::of::<Arc<T>>(),
+            type_name: std::any::type_name::<T>(),
+        }
+    }
+
+    /// The factory registered for the request-scoped service `T`.
+    pub fn scoped<T: ?Sized + Send + Sync + 'static>() -> Self {
+        Self::of::<dyn ScopedFactory<T>>()
+    }
+}
+
+#[derive(Debug)]
+pub struct MissingDependency(Dependency);
+
+impl Display for MissingDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no service is registered for `{}`", self.0.type_name)
+    }
+}
+
+impl std::error::Error for MissingDependency {}
+
+impl IntoResponse for MissingDependency {
+    fn into_response(self) -> Response {
+        tracing::error!(error = %self, "handler dependency could not be resolved");
+        Problem::new(StatusCode::INTERNAL_SERVER_ERROR).into_response()
+    }
+}
+
+/// Extracts a service registered in the state's [`Container`], rejecting the request with a 500
+/// if nothing was registered for `T`.
+pub struct Resolved<T: ?Sized>(pub Arc<T>);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for Resolved<T>
+where
+    Arc<Container>: FromRef<S>,
+    S: Send + Sync,
+    T: ?Sized + Send + Sync + 'static,
+{
+    type Rejection = MissingDependency;
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Arc::<Container>::from_ref(state).resolve::<T>().map(Resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    use axum::routing::get;
+    use axum::Router;
+
+    trait Greeter {
+        fn greet(&self) -> String;
+    }
+
+    struct English;
+
+    impl Greeter for English {
+        fn greet(&self) -> String {
+            "hello".to_string()
+        }
+    }
+
+    trait Mailer {}
+
+    #[test]
+    fn test_resolves_by_registered_type() {
+        let mut container = Container::default();
+        container.register::<dyn Greeter + Send + Sync>(Arc::new(English));
+
+        let greeter = container.resolve::<dyn Greeter + Send + Sync>().unwrap();
+        assert_eq!(greeter.greet(), "hello");
+
+        // The concrete type was never registered, only the trait object
+        assert!(container.resolve::<English>().is_err());
+    }
+
+    struct Shouting(Arc<dyn Greeter + Send + Sync>);
+
+    impl Greeter for Shouting {
+        fn greet(&self) -> String {
+            self.0.greet().to_uppercase()
+        }
+    }
+
+    #[test]
+    fn test_decorate_wraps_the_registered_service() {
+        let mut container = Container::default();
+        assert!(container
+            .decorate::<dyn Greeter + Send + Sync>(|inner| Arc::new(Shouting(inner)))
+            .is_err());
+
+        container.register::<dyn Greeter + Send + Sync>(Arc::new(English));
+        container
+            .decorate::<dyn Greeter + Send + Sync>(|inner| Arc::new(Shouting(inner)))
+            .unwrap();
+
+        let greeter = container.resolve::<dyn Greeter + Send + Sync>().unwrap();
+        assert_eq!(greeter.greet(), "HELLO");
+    }
+
+    #[test]
+    fn test_verify_names_the_missing_dependency() {
+        let mut container = Container::default();
+        container.register::<dyn Greeter + Send + Sync>(Arc::new(English));
+
+        assert!(container.verify(&[Dependency::of::<dyn Greeter + Send + Sync>()]).is_ok());
+
+        let err = container
+            .verify(&[
+                Dependency::of::<dyn Greeter + Send + Sync>(),
+                Dependency::of::<dyn Mailer + Send + Sync>(),
+            ])
+            .unwrap_err();
+        assert!(err.to_string().contains("Mailer"));
+    }
+
+    #[tokio::test]
+    async fn test_health_checks_see_the_current_registration() {
+        let mut container = Container::default();
+        container
+            .register::<dyn Greeter + Send + Sync>(Arc::new(English))
+            .register_health_check("greeter", |greeter: Arc<dyn Greeter + Send + Sync>| async move {
+                match greeter.greet().as_str() {
+                    "hello" => HealthStatus::Healthy,
+                    other => HealthStatus::unhealthy(other),
+                }
+            })
+            .register_health_check("mailer", |_: Arc<dyn Mailer + Send + Sync>| async { HealthStatus::Healthy });
+
+        container
+            .decorate::<dyn Greeter + Send + Sync>(|inner| Arc::new(Shouting(inner)))
+            .unwrap();
+
+        let checks = container.health_checks().await;
+        assert_eq!(checks["greeter"], HealthStatus::unhealthy("HELLO"));
+        assert!(!checks["mailer"].is_healthy());
+    }
+
+    async fn greet_handler(Resolved(greeter): Resolved<dyn Greeter + Send + Sync>) -> String {
+        greeter.greet()
+    }
+
+    #[tokio::test]
+    async fn test_resolved_extractor() {
+        let mut container = Container::default();
+        container.register::<dyn Greeter + Send + Sync>(Arc::new(English));
+
+        let app = Router::new()
+            .route("/", get(greet_handler))
+            .with_state(Arc::new(container));
+
+        let client = TestClient::new(app);
+
+        let res = client.get("/").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.text().await, "hello");
+    }
+
+    #[tokio::test]
+    async fn test_resolved_extractor_rejects_missing_services() {
+        let app = Router::new()
+            .route("/", get(greet_handler))
+            .with_state(Arc::new(Container::default()));
+
+        let client = TestClient::new(app);
+
+        let res = client.get("/").send().await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.headers()["content-type"], crate::problem::PROBLEM_JSON);
+    }
+}
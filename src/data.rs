@@ -0,0 +1,271 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use crate::validation::{Fields, Validate};
+
+pub const MAX_NAME_LEN: usize = 100;
+
+pub const MAX_TAGS: usize = 16;
+
+pub const MAX_TAG_LEN: usize = 32;
+
+/// Upper bound on the size of a record's payload once encoded as JSON.
+pub const MAX_PAYLOAD_BYTES: usize = 16 * 1024;
+
+/// A stored record. The timestamps and version are maintained by the server, clients only
+/// provide the fields in [`DataInput`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Data {
+    pub(crate) id: usize,
+    pub(crate) name: String,
+    pub(crate) tags: Vec<String>,
+    pub(crate) payload: Value,
+    #[serde(with = "time::serde::rfc3339")]
+    pub(crate) created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub(crate) updated_at: OffsetDateTime,
+    /// Starts at 1 and is incremented every time the record is updated.
+    pub(crate) version: u64,
+}
+
+impl Data {
+    /// A new record with `id` holding `input`, created now.
+    pub fn new(id: usize, input: DataInput) -> Self {
+        let now = OffsetDateTime::now_utc();
+
+        Self {
+            id,
+            name: input.name,
+            tags: input.tags,
+            payload: input.payload,
+            created_at: now,
+            updated_at: now,
+            version: 1,
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Which version of which incarnation of the record this is.
+    pub fn revision(&self) -> Revision {
+        Revision {
+            version: self.version,
+            created_at: self.created_at,
+        }
+    }
+
+    /// This record after being replaced by `replacement`, keeping its identity and creation time
+    /// and moving on to the next version.
+    pub fn revise(&self, replacement: Data) -> Data {
+        Data {
+            id: self.id,
+            created_at: self.created_at,
+            version: self.version + 1,
+            ..replacement
+        }
+    }
+}
+
+/// Identifies one state of a record. The version alone isn't enough, as a record that is deleted
+/// and created again under the same id starts over at version 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Revision {
+    pub version: u64,
+    pub created_at: OffsetDateTime,
+}
+
+/// The fields of a record that clients set when creating or replacing it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataInput {
+    pub name: String,
+    pub tags: Vec<String>,
+    pub payload: Value,
+}
+
+impl DataInput {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            tags: Vec::new(),
+            payload: Value::Null,
+        }
+    }
+}
+
+impl Validate for DataInput {
+    fn validate(fields: &mut Fields) -> Option<Self> {
+        let name = fields
+            .required::<String>("name")
+            .and_then(|name| fields.ensure("name", name, |name| validate_name(name)));
+        let tags = fields.optional::<Vec<String>>("tags").and_then(|tags| validate_tags(fields, tags));
+        let payload = fields
+            .optional::<Value>("payload")
+            .and_then(|payload| fields.ensure("payload", payload, validate_payload));
+
+        Some(DataInput {
+            name: name?,
+            tags: tags?,
+            payload: payload?,
+        })
+    }
+}
+
+/// The body of a `PATCH` request: only the fields that were sent are changed, the rest keep their
+/// stored values.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DataPatch {
+    pub name: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub payload: Option<Value>,
+}
+
+impl DataPatch {
+    /// The fields of `current` with this patch applied.
+    pub fn apply_to(&self, current: &Data) -> DataInput {
+        DataInput {
+            name: self.name.clone().unwrap_or_else(|| current.name.clone()),
+            tags: self.tags.clone().unwrap_or_else(|| current.tags.clone()),
+            payload: self.payload.clone().unwrap_or_else(|| current.payload.clone()),
+        }
+    }
+}
+
+impl Validate for DataPatch {
+    fn validate(fields: &mut Fields) -> Option<Self> {
+        let name = fields.present::<String>("name").and_then(|name| match name {
+            Some(name) => fields.ensure("name", name, |name| validate_name(name)).map(Some),
+            None => Some(None),
+        });
+        let tags = fields.present::<Vec<String>>("tags").and_then(|tags| match tags {
+            Some(tags) => validate_tags(fields, tags).map(Some),
+            None => Some(None),
+        });
+        let payload = fields.present::<Value>("payload").and_then(|payload| match payload {
+            Some(payload) => fields.ensure("payload", payload, validate_payload).map(Some),
+            None => Some(None),
+        });
+
+        Some(DataPatch {
+            name: name?,
+            tags: tags?,
+            payload: payload?,
+        })
+    }
+}
+
+/// The body of a create request: the id to store the record under along with its fields.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateData {
+    pub id: usize,
+    pub input: DataInput,
+}
+
+impl Validate for CreateData {
+    fn validate(fields: &mut Fields) -> Option<Self> {
+        let id = fields.required("id");
+        let input = DataInput::validate(fields);
+
+        Some(CreateData { id: id?, input: input? })
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        Err("must not be blank".to_string())
+    } else if name.chars().count() > MAX_NAME_LEN {
+        Err(format!("must be at most {MAX_NAME_LEN} characters"))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_tags(fields: &mut Fields, tags: Vec<String>) -> Option<Vec<String>> {
+    let errors_before = fields.error_count();
+
+    if tags.len() > MAX_TAGS {
+        fields.add_error("tags", format!("must have at most {MAX_TAGS} entries"));
+    }
+
+    let mut seen = HashSet::new();
+    for (index, tag) in tags.iter().enumerate() {
+        let valid_chars = tag
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+
+        if tag.is_empty() || tag.len() > MAX_TAG_LEN || !valid_chars {
+            fields.add_error(
+                format!("tags[{index}]"),
+                format!("must be 1 to {MAX_TAG_LEN} lowercase letters, digits or dashes"),
+            );
+        } else if !seen.insert(tag) {
+            fields.add_error(format!("tags[{index}]"), "is a duplicate");
+        }
+    }
+
+    (fields.error_count() == errors_before).then_some(tags)
+}
+
+fn validate_payload(payload: &Value) -> Result<(), String> {
+    let encoded_len = serde_json::to_vec(payload).map(|encoded| encoded.len()).unwrap_or(usize::MAX);
+
+    if encoded_len > MAX_PAYLOAD_BYTES {
+        Err(format!("must be at most {MAX_PAYLOAD_BYTES} bytes once encoded"))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_revise_keeps_identity_and_bumps_version() {
+        let original = Data::new(1, DataInput::new("first"));
+
+        let mut replacement = Data::new(99, DataInput::new("second"));
+        replacement.created_at = OffsetDateTime::UNIX_EPOCH;
+
+        let revised = original.revise(replacement);
+        assert_eq!(revised.id, 1);
+        assert_eq!(revised.name, "second");
+        assert_eq!(revised.created_at, original.created_at);
+        assert_eq!(revised.version, 2);
+    }
+
+    #[test]
+    fn test_patch_only_changes_the_fields_it_sets() {
+        let mut current = Data::new(1, DataInput::new("first"));
+        current.tags = vec!["red".to_string()];
+        current.payload = serde_json::json!({"weight": 1});
+
+        let patch = DataPatch {
+            name: Some("second".to_string()),
+            payload: Some(Value::Null),
+            ..DataPatch::default()
+        };
+
+        let input = patch.apply_to(&current);
+        assert_eq!(input.name, "second");
+        assert_eq!(input.tags, vec!["red".to_string()]);
+        assert_eq!(input.payload, Value::Null);
+    }
+
+    #[test]
+    fn test_serializes_timestamps_as_rfc3339() {
+        let mut data = Data::new(1, DataInput::new("first"));
+        data.created_at = OffsetDateTime::UNIX_EPOCH;
+
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["created_at"], "1970-01-01T00:00:00Z");
+        assert_eq!(json["version"], 1);
+
+        let parsed: Data = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, data);
+    }
+}
@@ -0,0 +1,13 @@
+mod cached;
+mod coalescing;
+mod in_memory;
+mod instrumented;
+mod resilient;
+mod sqlite;
+
+pub use cached::*;
+pub use coalescing::*;
+pub(crate) use in_memory::*;
+pub(crate) use instrumented::*;
+pub use resilient::*;
+pub(crate) use sqlite::*;
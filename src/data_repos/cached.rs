@@ -0,0 +1,351 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::async_trait;
+use lru::LruCache;
+
+use crate::batch::MismatchedBatch;
+use crate::config::CacheConfig;
+use crate::health::HealthStatus;
+use crate::listing::{ListQuery, Page};
+use crate::metrics::Metrics;
+use crate::{Data, DataRepo, DataRepoError, DynDataRepo, Revision};
+
+#[derive(Clone)]
+enum Cached {
+    Found(Data),
+    /// The inner repo reported the record as missing.
+    Missing,
+}
+
+struct Entry {
+    value: Cached,
+    expires_at: Instant,
+}
+
+struct CacheState {
+    entries: LruCache<usize, Entry>,
+    /// Bumped by every write. A lookup only fills the cache if no write happened while it was
+    /// reading from the inner repo, otherwise it could store a record the write just replaced.
+    generation: u64,
+}
+
+/// Wraps another [`DataRepo`] with a bounded LRU cache of individual records. Entries expire
+/// after a time to live, records the inner repo reports as missing are remembered for a shorter
+/// one, and every write through this repo invalidates the record it touched.
+///
+/// Writes made to the inner repo by other processes are only seen once the cached entry expires.
+/// Listings always go to the inner repo.
+pub struct CachedDataRepo<R = DynDataRepo> {
+    inner: R,
+    metrics: Arc<Metrics>,
+    ttl: Duration,
+    negative_ttl: Duration,
+    state: Mutex<CacheState>,
+}
+
+impl<R: DataRepo + Send + Sync> CachedDataRepo<R> {
+    pub fn new(inner: R, config: &CacheConfig, metrics: Arc<Metrics>) -> Self {
+        let capacity = NonZeroUsize::new(config.capacity).unwrap_or(NonZeroUsize::MIN);
+
+        Self {
+            inner,
+            metrics,
+            ttl: config.ttl(),
+            negative_ttl: config.negative_ttl(),
+            state: Mutex::new(CacheState {
+                entries: LruCache::new(capacity),
+                generation: 0,
+            }),
+        }
+    }
+
+    /// The cached value for `id` if there is a live one, along with the generation the cache was
+    /// at when it was checked.
+    fn lookup(&self, id: usize) -> (Option<Cached>, u64) {
+        let mut state = self.state.lock().unwrap();
+
+        let value = match state.entries.get(&id) {
+            Some(entry) if entry.expires_at > Instant::now() => Some(entry.value.clone()),
+            Some(_) => {
+                state.entries.pop(&id);
+                None
+            }
+            None => None,
+        };
+
+        (value, state.generation)
+    }
+
+    fn fill(&self, id: usize, value: Cached, generation: u64) {
+        let ttl = match value {
+            Cached::Found(_) => self.ttl,
+            Cached::Missing => self.negative_ttl,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if state.generation == generation {
+            let expires_at = Instant::now() + ttl;
+            state.entries.put(id, Entry { value, expires_at });
+        }
+    }
+
+    fn invalidate(&self, id: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state.entries.pop(&id);
+    }
+}
+
+#[async_trait]
+impl<R: DataRepo + Send + Sync> DataRepo for CachedDataRepo<R> {
+    async fn create(&self, data: Data) -> Result<Data, DataRepoError> {
+        let id = data.id;
+        let result = self.inner.create(data).await;
+        self.invalidate(id);
+
+        result
+    }
+
+    async fn retrieve(&self, id: usize) -> Result<Data, DataRepoError> {
+        let (cached, generation) = self.lookup(id);
+
+        match cached {
+            Some(Cached::Found(data)) => {
+                self.metrics.record_cache_lookup("hit");
+                return Ok(data);
+            }
+            Some(Cached::Missing) => {
+                self.metrics.record_cache_lookup("negative_hit");
+                return Err(DataRepoError::NotFound);
+            }
+            None => self.metrics.record_cache_lookup("miss"),
+        }
+
+        let result = self.inner.retrieve(id).await;
+        match &result {
+            Ok(data) => self.fill(id, Cached::Found(data.clone()), generation),
+            Err(DataRepoError::NotFound) => self.fill(id, Cached::Missing, generation),
+            // Transient failures are not worth remembering
+            Err(_) => {}
+        }
+
+        result
+    }
+
+    async fn retrieve_many(&self, ids: &[usize]) -> Vec<Result<Data, DataRepoError>> {
+        let mut results = Vec::with_capacity(ids.len());
+        let mut misses = Vec::new();
+        let mut generation = 0;
+
+        for &id in ids {
+            let (cached, current) = self.lookup(id);
+            generation = current;
+
+            results.push(match cached {
+                Some(Cached::Found(data)) => {
+                    self.metrics.record_cache_lookup("hit");
+                    Some(Ok(data))
+                }
+                Some(Cached::Missing) => {
+                    self.metrics.record_cache_lookup("negative_hit");
+                    Some(Err(DataRepoError::NotFound))
+                }
+                None => {
+                    self.metrics.record_cache_lookup("miss");
+                    misses.push(id);
+                    None
+                }
+            });
+        }
+
+        let fetched = if misses.is_empty() {
+            Vec::new()
+        } else {
+            self.inner.retrieve_many(&misses).await
+        };
+
+        if fetched.len() != misses.len() {
+            let err = DataRepoError::internal(MismatchedBatch {
+                requested: misses.len(),
+                answered: fetched.len(),
+            });
+            return results
+                .into_iter()
+                .map(|cached| cached.unwrap_or_else(|| Err(err.clone())))
+                .collect();
+        }
+
+        // Writes before the last lookup happened before the fetch too, so only later ones matter
+        for (&id, result) in misses.iter().zip(&fetched) {
+            match result {
+                Ok(data) => self.fill(id, Cached::Found(data.clone()), generation),
+                Err(DataRepoError::NotFound) => self.fill(id, Cached::Missing, generation),
+                Err(_) => {}
+            }
+        }
+
+        // Every miss has exactly one answer, taken in the order the misses were collected
+        let mut fetched = fetched.into_iter();
+        results
+            .into_iter()
+            .filter_map(|cached| cached.or_else(|| fetched.next()))
+            .collect()
+    }
+
+    async fn update(&self, id: usize, data: Data, expected: Option<Revision>) -> Result<Data, DataRepoError> {
+        let result = self.inner.update(id, data, expected).await;
+        self.invalidate(id);
+
+        result
+    }
+
+    async fn delete(&self, id: usize) -> Result<(), DataRepoError> {
+        let result = self.inner.delete(id).await;
+        self.invalidate(id);
+
+        result
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<Page<Data>, DataRepoError> {
+        self.inner.list(query).await
+    }
+
+    async fn health_check(&self) -> HealthStatus {
+        self.inner.health_check().await
+    }
+
+    async fn shutdown(&self) {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn config(capacity: usize) -> CacheConfig {
+        CacheConfig {
+            enabled: true,
+            capacity,
+            ttl_secs: 60,
+            negative_ttl_secs: 60,
+        }
+    }
+
+    fn cached(inner: MockDataRepo, capacity: usize) -> (CachedDataRepo<MockDataRepo>, Arc<Metrics>) {
+        let metrics = Arc::new(Metrics::default());
+        (CachedDataRepo::new(inner, &config(capacity), metrics.clone()), metrics)
+    }
+
+    #[tokio::test]
+    async fn test_hits_skip_the_inner_repo() {
+        let inner = MockDataRepo::new().reply_for(1, Reply::ok(data(1)));
+        let (repo, metrics) = cached(inner.clone(), 8);
+
+        for _ in 0..3 {
+            assert_eq!(repo.retrieve(1).await.unwrap().id, 1);
+        }
+
+        // Nothing is scripted for 2, so the mock reports it missing
+        for _ in 0..3 {
+            assert!(matches!(repo.retrieve(2).await, Err(DataRepoError::NotFound)));
+        }
+
+        inner.assert_call_count(2);
+
+        let rendered = metrics.render().unwrap();
+        assert!(rendered.contains(r#"data_repo_cache_lookups_total{result="hit"} 2"#));
+        assert!(rendered.contains(r#"data_repo_cache_lookups_total{result="negative_hit"} 2"#));
+        assert!(rendered.contains(r#"data_repo_cache_lookups_total{result="miss"} 2"#));
+    }
+
+    #[tokio::test]
+    async fn test_writes_invalidate_the_record() {
+        let inner = MockDataRepo::new().reply(Reply::ok(data(1)));
+        let (repo, _) = cached(inner.clone(), 8);
+
+        repo.retrieve(1).await.unwrap();
+        repo.update(1, data(1), None).await.unwrap();
+        repo.retrieve(1).await.unwrap();
+        repo.delete(1).await.unwrap();
+        repo.retrieve(1).await.unwrap();
+        repo.create(data(1)).await.unwrap();
+        repo.retrieve(1).await.unwrap();
+        repo.retrieve(1).await.unwrap();
+
+        let retrieves = inner
+            .calls()
+            .iter()
+            .filter(|call| matches!(call, DataRepoCall::Retrieve(_)))
+            .count();
+        assert_eq!(retrieves, 4);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_many_only_fetches_misses() {
+        let inner = MockDataRepo::new()
+            .reply_for(1, Reply::ok(data(1)))
+            .reply_for(2, Reply::ok(data(2)));
+        let (repo, _) = cached(inner.clone(), 8);
+
+        repo.retrieve(1).await.unwrap();
+
+        let results = repo.retrieve_many(&[1, 2, 3]).await;
+        assert_eq!(results[0].as_ref().unwrap().id, 1);
+        assert_eq!(results[1].as_ref().unwrap().id, 2);
+        assert!(matches!(results[2], Err(DataRepoError::NotFound)));
+        inner.assert_call_count(3);
+
+        repo.retrieve_many(&[1, 2, 3]).await;
+        inner.assert_call_count(3);
+    }
+
+    #[tokio::test]
+    async fn test_capacity_evicts_the_least_recently_used() {
+        let inner = MockDataRepo::new().reply(Reply::ok(data(1)));
+        let (repo, _) = cached(inner.clone(), 2);
+
+        repo.retrieve(1).await.unwrap();
+        repo.retrieve(2).await.unwrap();
+        repo.retrieve(1).await.unwrap();
+        repo.retrieve(3).await.unwrap();
+        inner.assert_call_count(3);
+
+        // 2 was the least recently used when 3 came in
+        repo.retrieve(1).await.unwrap();
+        inner.assert_call_count(3);
+        repo.retrieve(2).await.unwrap();
+        inner.assert_call_count(4);
+    }
+
+    #[tokio::test]
+    async fn test_entries_expire() {
+        let inner = MockDataRepo::new().reply(Reply::ok(data(1)));
+        let metrics = Arc::new(Metrics::default());
+        let config = CacheConfig {
+            ttl_secs: 0,
+            ..config(8)
+        };
+        let repo = CachedDataRepo::new(inner.clone(), &config, metrics);
+
+        repo.retrieve(1).await.unwrap();
+        repo.retrieve(1).await.unwrap();
+        inner.assert_call_count(2);
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors_are_not_cached() {
+        let inner = MockDataRepo::new()
+            .reply(Reply::err(DataRepoError::Unavailable))
+            .reply(Reply::ok(data(1)));
+        let (repo, _) = cached(inner.clone(), 8);
+
+        assert!(matches!(repo.retrieve(1).await, Err(DataRepoError::Unavailable)));
+        assert_eq!(repo.retrieve(1).await.unwrap().id, 1);
+        assert_eq!(repo.retrieve(1).await.unwrap().id, 1);
+        inner.assert_call_count(2);
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use axum::async_trait;
+use futures::future::{BoxFuture, FutureExt, Shared};
+
+use crate::health::HealthStatus;
+use crate::listing::{ListQuery, Page};
+use crate::{Data, DataRepo, DataRepoError, DynDataRepo, Revision};
+
+type Flight = Shared<BoxFuture<'static, Result<Data, DataRepoError>>>;
+
+#[derive(Default)]
+struct Flights {
+    by_id: HashMap<usize, (u64, Flight)>,
+    next_flight: u64,
+}
+
+/// Takes a flight out of the registry when its call ends, however it ends. Flights that were
+/// detached and replaced by a newer one are left alone.
+struct Landing {
+    registry: Arc<Mutex<Flights>>,
+    id: usize,
+    flight_id: u64,
+}
+
+impl Drop for Landing {
+    fn drop(&mut self) {
+        // This also runs while unwinding from a panicking call, where a second panic would abort
+        let mut flights = self.registry.lock().unwrap_or_else(PoisonError::into_inner);
+        if matches!(flights.by_id.get(&self.id), Some((current, _)) if *current == self.flight_id) {
+            flights.by_id.remove(&self.id);
+        }
+    }
+}
+
+/// Wraps another [`DataRepo`] so concurrent `retrieve` calls for the same id share a single call
+/// to it, with every caller getting a copy of its result.
+///
+/// The shared call runs on its own task, so it finishes even if the caller that started it goes
+/// away. A write to a record detaches the retrieve in flight for it, so callers arriving after the
+/// write start a fresh call rather than waiting on one that may return the old record.
+pub struct CoalescingDataRepo<R = DynDataRepo> {
+    inner: Arc<R>,
+    flights: Arc<Mutex<Flights>>,
+}
+
+impl<R: DataRepo + Send + Sync + 'static> CoalescingDataRepo<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            flights: Arc::default(),
+        }
+    }
+
+    /// The call in flight for `id`, starting one if there is none.
+    fn join(&self, id: usize) -> Flight {
+        let mut flights = self.flights.lock().unwrap();
+
+        if let Some((_, flight)) = flights.by_id.get(&id) {
+            return flight.clone();
+        }
+
+        let flight_id = flights.next_flight;
+        flights.next_flight += 1;
+
+        let inner = self.inner.clone();
+        let landing = Landing {
+            registry: self.flights.clone(),
+            id,
+            flight_id,
+        };
+        let call = tokio::spawn(async move {
+            let _landing = landing;
+            inner.retrieve(id).await
+        });
+
+        let flight = async move { call.await.unwrap_or_else(|err| Err(DataRepoError::internal(err))) }
+            .boxed()
+            .shared();
+        flights.by_id.insert(id, (flight_id, flight.clone()));
+
+        flight
+    }
+
+    fn detach(&self, id: usize) {
+        self.flights.lock().unwrap().by_id.remove(&id);
+    }
+}
+
+#[async_trait]
+impl<R: DataRepo + Send + Sync + 'static> DataRepo for CoalescingDataRepo<R> {
+    async fn create(&self, data: Data) -> Result<Data, DataRepoError> {
+        let id = data.id;
+        let result = self.inner.create(data).await;
+        self.detach(id);
+
+        result
+    }
+
+    async fn retrieve(&self, id: usize) -> Result<Data, DataRepoError> {
+        self.join(id).await
+    }
+
+    /// Batches go straight to the inner repo, keeping whatever single round trip it offers.
+    async fn retrieve_many(&self, ids: &[usize]) -> Vec<Result<Data, DataRepoError>> {
+        self.inner.retrieve_many(ids).await
+    }
+
+    async fn update(&self, id: usize, data: Data, expected: Option<Revision>) -> Result<Data, DataRepoError> {
+        self.detach(id);
+        let result = self.inner.update(id, data, expected).await;
+        self.detach(id);
+
+        result
+    }
+
+    async fn delete(&self, id: usize) -> Result<(), DataRepoError> {
+        self.detach(id);
+        let result = self.inner.delete(id).await;
+        self.detach(id);
+
+        result
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<Page<Data>, DataRepoError> {
+        self.inner.list(query).await
+    }
+
+    async fn health_check(&self) -> HealthStatus {
+        self.inner.health_check().await
+    }
+
+    async fn shutdown(&self) {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    use std::time::Duration;
+
+    fn slow_repo() -> MockDataRepo {
+        MockDataRepo::new()
+            .reply_for(1, Reply::ok(data(1)).after(Duration::from_millis(100)))
+            .reply_for(2, Reply::err(DataRepoError::NotFound).after(Duration::from_millis(100)))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_retrieves_share_one_call() {
+        let inner = slow_repo();
+        let repo = CoalescingDataRepo::new(inner.clone());
+
+        let results = futures::future::join_all((0..10).map(|_| repo.retrieve(1))).await;
+        assert!(results.iter().all(|result| result.as_ref().unwrap().id == 1));
+
+        let results = futures::future::join_all((0..10).map(|_| repo.retrieve(2))).await;
+        assert!(results.iter().all(|result| matches!(result, Err(DataRepoError::NotFound))));
+
+        inner.assert_call_count(2);
+
+        // Once a call has finished the next retrieve goes to the backend again
+        repo.retrieve(1).await.unwrap();
+        inner.assert_call_count(3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_the_call_survives_its_caller() {
+        let inner = slow_repo();
+        let repo = CoalescingDataRepo::new(inner.clone());
+
+        let abandoned = tokio::time::timeout(Duration::from_millis(10), repo.retrieve(1)).await;
+        assert!(abandoned.is_err());
+
+        assert_eq!(repo.retrieve(1).await.unwrap().id, 1);
+        inner.assert_call_count(1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_writes_detach_the_call_in_flight() {
+        let inner = slow_repo().reply_for(1, Reply::ok(data(1)));
+        let repo = Arc::new(CoalescingDataRepo::new(inner.clone()));
+
+        let before = tokio::spawn({
+            let repo = repo.clone();
+            async move { repo.retrieve(1).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        repo.update(1, data(1), None).await.unwrap();
+        let after = repo.retrieve(1).await;
+
+        assert!(before.await.unwrap().is_ok());
+        assert!(after.is_ok());
+
+        let retrieves = inner
+            .calls()
+            .iter()
+            .filter(|call| matches!(call, DataRepoCall::Retrieve(_)))
+            .count();
+        assert_eq!(retrieves, 2);
+    }
+
+    #[tokio::test]
+    async fn test_panics_are_reported_to_every_waiter() {
+        let inner = MockDataRepo::new()
+            .reply(Reply::panic("backend bug").after(Duration::from_millis(50)))
+            .reply(Reply::ok(data(1)));
+        let repo = CoalescingDataRepo::new(inner.clone());
+
+        let results = futures::future::join_all((0..3).map(|_| repo.retrieve(1))).await;
+        assert!(results.iter().all(|result| matches!(result, Err(DataRepoError::Internal(_)))));
+        inner.assert_call_count(1);
+
+        // The failed call isn't shared with later retrieves, they make a fresh one
+        assert_eq!(repo.retrieve(1).await.unwrap().id, 1);
+        inner.assert_call_count(2);
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use axum::async_trait;
+use tokio::sync::RwLock;
+
+use crate::listing::{ListQuery, Page};
+use crate::{Data, DataRepo, DataRepoError, Revision};
+
+/// A [`DataRepo`] that keeps every record in process memory. Records are lost when the process
+/// exits, which makes this a convenient stand-in for a real backend during local development and
+/// tests. Seed it by collecting an iterator of [`Data`] into it.
+#[derive(Default)]
+pub(crate) struct InMemoryDataRepo {
+    records: RwLock<HashMap<usize, Data>>,
+}
+
+#[async_trait]
+impl DataRepo for InMemoryDataRepo {
+    async fn create(&self, data: Data) -> Result<Data, DataRepoError> {
+        match self.records.write().await.entry(data.id) {
+            Entry::Occupied(_) => Err(DataRepoError::Conflict),
+            Entry::Vacant(entry) => Ok(entry.insert(data).clone()),
+        }
+    }
+
+    async fn retrieve(&self, id: usize) -> Result<Data, DataRepoError> {
+        self.records
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .ok_or(DataRepoError::NotFound)
+    }
+
+    async fn retrieve_many(&self, ids: &[usize]) -> Vec<Result<Data, DataRepoError>> {
+        let records = self.records.read().await;
+
+        ids.iter()
+            .map(|id| records.get(id).cloned().ok_or(DataRepoError::NotFound))
+            .collect()
+    }
+
+    async fn update(&self, id: usize, data: Data, expected: Option<Revision>) -> Result<Data, DataRepoError> {
+        let mut records = self.records.write().await;
+        let record = records.get_mut(&id).ok_or(DataRepoError::NotFound)?;
+
+        if let Some(expected) = expected {
+            if record.revision() != expected {
+                return Err(DataRepoError::PreconditionFailed);
+            }
+        }
+
+        *record = record.revise(data);
+
+        Ok(record.clone())
+    }
+
+    async fn delete(&self, id: usize) -> Result<(), DataRepoError> {
+        match self.records.write().await.remove(&id) {
+            Some(_) => Ok(()),
+            None => Err(DataRepoError::NotFound),
+        }
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<Page<Data>, DataRepoError> {
+        Ok(query.paginate(self.records.read().await.values().cloned()))
+    }
+}
+
+impl FromIterator<Data> for InMemoryDataRepo {
+    fn from_iter<I: IntoIterator<Item = Data>>(iter: I) -> Self {
+        let records = iter.into_iter().map(|data| (data.id, data)).collect();
+
+        Self {
+            records: RwLock::new(records),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listing::NameFilter;
+    use crate::test_helpers::data;
+    use crate::DataInput;
+
+    #[tokio::test]
+    async fn test_create_then_retrieve() {
+        let repo = InMemoryDataRepo::default();
+
+        let created = repo.create(data(7)).await.unwrap();
+        assert_eq!(created.id, 7);
+
+        let found = repo.retrieve(7).await.unwrap();
+        assert_eq!(found.id, 7);
+
+        assert!(matches!(repo.retrieve(8).await, Err(DataRepoError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_create_rejects_duplicates() {
+        let repo: InMemoryDataRepo = [data(1)].into_iter().collect();
+
+        assert!(matches!(repo.create(data(1)).await, Err(DataRepoError::Conflict)));
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete() {
+        let repo: InMemoryDataRepo = [data(1), data(2)].into_iter().collect();
+
+        let updated = repo.update(1, data(99), None).await.unwrap();
+        assert_eq!(updated.id, 1);
+        assert_eq!(updated.name, "record 99");
+        assert_eq!(updated.version, 2);
+        assert_eq!(repo.retrieve(1).await.unwrap(), updated);
+        assert!(matches!(
+            repo.update(3, data(3), None).await,
+            Err(DataRepoError::NotFound)
+        ));
+
+        assert!(repo.delete(2).await.is_ok());
+        assert!(matches!(repo.delete(2).await, Err(DataRepoError::NotFound)));
+        assert!(matches!(repo.retrieve(2).await, Err(DataRepoError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_many_keeps_the_requested_order() {
+        let repo: InMemoryDataRepo = [data(1), data(2)].into_iter().collect();
+
+        let results = repo.retrieve_many(&[2, 3, 1]).await;
+        assert_eq!(results[0].as_ref().unwrap().id, 2);
+        assert!(matches!(results[1], Err(DataRepoError::NotFound)));
+        assert_eq!(results[2].as_ref().unwrap().id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_checks_the_expected_revision() {
+        let repo: InMemoryDataRepo = [data(1)].into_iter().collect();
+        let first = repo.retrieve(1).await.unwrap().revision();
+        let later = Revision { version: 2, ..first };
+
+        assert!(matches!(
+            repo.update(1, data(1), Some(later)).await,
+            Err(DataRepoError::PreconditionFailed)
+        ));
+        assert_eq!(repo.update(1, data(1), Some(first)).await.unwrap().version, 2);
+        assert!(matches!(
+            repo.update(1, data(1), Some(first)).await,
+            Err(DataRepoError::PreconditionFailed)
+        ));
+        assert_eq!(repo.retrieve(1).await.unwrap().version, 2);
+
+        // A record deleted and created again is back at version 1, but isn't the one `first` saw
+        repo.delete(1).await.unwrap();
+        let recreated = Data {
+            created_at: first.created_at + time::Duration::seconds(1),
+            ..data(1)
+        };
+        repo.create(recreated).await.unwrap();
+        assert!(matches!(
+            repo.update(1, data(1), Some(first)).await,
+            Err(DataRepoError::PreconditionFailed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_is_sorted_by_id() {
+        let repo: InMemoryDataRepo = [data(3), data(1), data(2)]
+            .into_iter()
+            .collect();
+
+        let page = repo.list(&ListQuery::default()).await.unwrap();
+        let ids: Vec<usize> = page.items.into_iter().map(|data| data.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_list_filters_on_name_and_tags() {
+        let mut input = DataInput::new("apricot");
+        input.tags = vec!["red".to_string()];
+        let repo: InMemoryDataRepo = [data(1), data(12), Data::new(2, input)].into_iter().collect();
+
+        let ids = |page: Page<Data>| page.items.into_iter().map(|data| data.id).collect::<Vec<_>>();
+
+        let mut query = ListQuery::default();
+        query.filter.name = Some(NameFilter::Prefix("record 1".to_string()));
+        assert_eq!(ids(repo.list(&query).await.unwrap()), vec![1, 12]);
+
+        query.filter.name = Some(NameFilter::Exact("record 1".to_string()));
+        assert_eq!(ids(repo.list(&query).await.unwrap()), vec![1]);
+
+        let mut query = ListQuery::default();
+        query.filter.tag = Some("red".to_string());
+        assert_eq!(ids(repo.list(&query).await.unwrap()), vec![2]);
+    }
+}
@@ -0,0 +1,104 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::async_trait;
+
+use crate::health::HealthStatus;
+use crate::listing::{ListQuery, Page};
+use crate::metrics::Metrics;
+use crate::{Data, DataRepo, DataRepoError, DynDataRepo, Revision};
+
+/// Wraps another [`DataRepo`] and records a call counter and latency histogram for each
+/// operation, labelled by the outcome of the call.
+pub(crate) struct InstrumentedDataRepo {
+    inner: DynDataRepo,
+    metrics: Arc<Metrics>,
+}
+
+impl InstrumentedDataRepo {
+    pub(crate) fn new(inner: DynDataRepo, metrics: Arc<Metrics>) -> Self {
+        Self { inner, metrics }
+    }
+
+    async fn observe<T>(
+        &self,
+        operation: &'static str,
+        call: impl Future<Output = Result<T, DataRepoError>>,
+    ) -> Result<T, DataRepoError> {
+        let start = Instant::now();
+        let result = call.await;
+
+        let outcome = match &result {
+            Ok(_) => "ok",
+            Err(err) => err.kind(),
+        };
+        self.metrics.record_repo_call(operation, outcome, start.elapsed());
+
+        result
+    }
+}
+
+#[async_trait]
+impl DataRepo for InstrumentedDataRepo {
+    async fn create(&self, data: Data) -> Result<Data, DataRepoError> {
+        self.observe("create", self.inner.create(data)).await
+    }
+
+    async fn retrieve(&self, id: usize) -> Result<Data, DataRepoError> {
+        self.observe("retrieve", self.inner.retrieve(id)).await
+    }
+
+    async fn retrieve_many(&self, ids: &[usize]) -> Vec<Result<Data, DataRepoError>> {
+        let start = Instant::now();
+        let results = self.inner.retrieve_many(ids).await;
+
+        // Individual records can fail without the call failing, those are reported per id
+        self.metrics.record_repo_call("retrieve_many", "ok", start.elapsed());
+
+        results
+    }
+
+    async fn update(&self, id: usize, data: Data, expected: Option<Revision>) -> Result<Data, DataRepoError> {
+        self.observe("update", self.inner.update(id, data, expected)).await
+    }
+
+    async fn delete(&self, id: usize) -> Result<(), DataRepoError> {
+        self.observe("delete", self.inner.delete(id)).await
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<Page<Data>, DataRepoError> {
+        self.observe("list", self.inner.list(query)).await
+    }
+
+    async fn health_check(&self) -> HealthStatus {
+        self.inner.health_check().await
+    }
+
+    async fn shutdown(&self) {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::data;
+    use crate::data_repos::InMemoryDataRepo;
+
+    #[tokio::test]
+    async fn test_calls_are_recorded_by_outcome() {
+        let metrics = Arc::new(Metrics::default());
+        let inner: InMemoryDataRepo = [data(1)].into_iter().collect();
+        let repo = InstrumentedDataRepo::new(Arc::new(inner), metrics.clone());
+
+        repo.retrieve(1).await.unwrap();
+        repo.retrieve(1).await.unwrap();
+        assert!(matches!(repo.retrieve(2).await, Err(DataRepoError::NotFound)));
+
+        let rendered = metrics.render().unwrap();
+        assert!(rendered.contains(r#"data_repo_calls_total{operation="retrieve",outcome="ok"} 2"#));
+        assert!(rendered.contains(r#"data_repo_calls_total{operation="retrieve",outcome="not_found"} 1"#));
+        assert!(rendered.contains(r#"data_repo_call_duration_seconds_count{operation="retrieve"} 3"#));
+    }
+}
@@ -0,0 +1,436 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use axum::async_trait;
+use rand::Rng;
+use tokio::time::Instant;
+
+use crate::config::ResilienceConfig;
+use crate::health::HealthStatus;
+use crate::listing::{ListQuery, Page};
+use crate::{Data, DataRepo, DataRepoError, DynDataRepo, Revision};
+
+enum BreakerState {
+    Closed { consecutive_failures: u32 },
+    Open { until: Instant },
+    /// The open period is over and a single probe call is deciding whether to close again.
+    HalfOpen { probing: bool },
+}
+
+/// Stops calls reaching a backend that keeps failing, giving it time to recover instead of piling
+/// more load and more slow requests onto it.
+struct CircuitBreaker {
+    failure_threshold: u32,
+    open_for: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    fn new(failure_threshold: u32, open_for: Duration) -> Self {
+        Self {
+            failure_threshold,
+            open_for,
+            state: Mutex::new(BreakerState::Closed { consecutive_failures: 0 }),
+        }
+    }
+
+    /// A permit for a call to go ahead, or `None` if it should fail fast. Once the open period is
+    /// over a single call is let through as a probe, the rest keep failing fast until it reports
+    /// back.
+    fn allow(&self) -> Option<Permit<'_>> {
+        let mut state = self.state.lock().unwrap();
+
+        let probe = match *state {
+            BreakerState::Closed { .. } => false,
+            BreakerState::Open { until } if Instant::now() < until => return None,
+            BreakerState::Open { .. } | BreakerState::HalfOpen { probing: false } => {
+                *state = BreakerState::HalfOpen { probing: true };
+                true
+            }
+            BreakerState::HalfOpen { probing: true } => return None,
+        };
+
+        Some(Permit {
+            breaker: self,
+            probe,
+            recorded: false,
+        })
+    }
+
+    /// Reports how a call let through by [`allow`](Self::allow) went.
+    fn record(&self, probe: bool, succeeded: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        // Only the probe decides whether an open breaker closes again. A call that was let through
+        // before the breaker opened says nothing about whether the backend has recovered since.
+        if !probe && !matches!(*state, BreakerState::Closed { .. }) {
+            return;
+        }
+
+        *state = match (&*state, succeeded) {
+            (_, true) => BreakerState::Closed { consecutive_failures: 0 },
+            (BreakerState::Closed { consecutive_failures }, false) if consecutive_failures + 1 < self.failure_threshold => {
+                BreakerState::Closed {
+                    consecutive_failures: consecutive_failures + 1,
+                }
+            }
+            (_, false) => {
+                tracing::warn!(open_for = ?self.open_for, "data repo circuit breaker opened");
+                BreakerState::Open {
+                    until: Instant::now() + self.open_for,
+                }
+            }
+        };
+    }
+
+    fn is_open(&self) -> bool {
+        !matches!(*self.state.lock().unwrap(), BreakerState::Closed { .. })
+    }
+}
+
+/// Lets one call through a [`CircuitBreaker`], which must be told how the call went.
+struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    /// Whether this call is the probe deciding if a half-open breaker closes again.
+    probe: bool,
+    recorded: bool,
+}
+
+impl Permit<'_> {
+    fn record(mut self, succeeded: bool) {
+        self.recorded = true;
+        self.breaker.record(self.probe, succeeded);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if !self.probe || self.recorded {
+            return;
+        }
+
+        // The probe was abandoned mid-call, e.g. because its request timed out or the client went
+        // away. Without this the breaker would wait for it forever.
+        let mut state = self.breaker.state.lock().unwrap();
+        if matches!(*state, BreakerState::HalfOpen { probing: true }) {
+            *state = BreakerState::Open {
+                until: Instant::now() + self.breaker.open_for,
+            };
+        }
+    }
+}
+
+/// Whether an error means the backend itself is struggling, as opposed to the request being one
+/// it can't satisfy.
+fn is_backend_failure(err: &DataRepoError) -> bool {
+    matches!(
+        err,
+        DataRepoError::Unavailable | DataRepoError::Timeout | DataRepoError::Internal(_)
+    )
+}
+
+fn is_retryable(err: &DataRepoError) -> bool {
+    matches!(err, DataRepoError::Unavailable | DataRepoError::Timeout)
+}
+
+/// Wraps another [`DataRepo`] so a slow or failing backend can't hold requests hostage. Every
+/// call is given a deadline, reads that fail with a retryable error are retried a bounded number
+/// of times with jittered exponential backoff, and once the backend has failed enough times in a
+/// row a circuit breaker fails calls straight away with [`DataRepoError::Unavailable`] until it
+/// has had a chance to recover.
+///
+/// Writes are not retried, since a write that timed out may still have been applied.
+pub struct ResilientDataRepo<R = DynDataRepo> {
+    inner: R,
+    call_timeout: Duration,
+    max_retries: u32,
+    backoff_base: Duration,
+    backoff_max: Duration,
+    breaker: CircuitBreaker,
+}
+
+impl<R: DataRepo + Send + Sync> ResilientDataRepo<R> {
+    pub fn new(inner: R, config: &ResilienceConfig) -> Self {
+        Self {
+            inner,
+            call_timeout: config.call_timeout(),
+            max_retries: config.max_retries,
+            backoff_base: config.backoff_base(),
+            backoff_max: config.backoff_max(),
+            breaker: CircuitBreaker::new(config.breaker_failure_threshold, config.breaker_open()),
+        }
+    }
+
+    /// Makes a single attempt at `call`, subject to the deadline and the circuit breaker.
+    async fn guarded<T>(&self, call: impl Future<Output = Result<T, DataRepoError>>) -> Result<T, DataRepoError> {
+        let Some(permit) = self.breaker.allow() else {
+            return Err(DataRepoError::Unavailable);
+        };
+
+        let result = tokio::time::timeout(self.call_timeout, call)
+            .await
+            .unwrap_or(Err(DataRepoError::Timeout));
+
+        permit.record(!matches!(&result, Err(err) if is_backend_failure(err)));
+
+        result
+    }
+
+    async fn retried<T, F, Fut>(&self, mut call: F) -> Result<T, DataRepoError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, DataRepoError>>,
+    {
+        let mut attempt = 0;
+
+        loop {
+            match self.guarded(call()).await {
+                Err(err) if is_retryable(&err) && attempt < self.max_retries && !self.breaker.is_open() => {
+                    tokio::time::sleep(self.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// A random delay of up to `base * 2^attempt`, capped at the configured maximum. Spreading
+    /// retries out this way keeps clients that failed together from retrying together.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .backoff_base
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.backoff_max);
+
+        ceiling.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+#[async_trait]
+impl<R: DataRepo + Send + Sync> DataRepo for ResilientDataRepo<R> {
+    async fn create(&self, data: Data) -> Result<Data, DataRepoError> {
+        self.guarded(self.inner.create(data)).await
+    }
+
+    async fn retrieve(&self, id: usize) -> Result<Data, DataRepoError> {
+        self.retried(|| self.inner.retrieve(id)).await
+    }
+
+    /// Makes one call to the inner repo for the whole batch, so the backend keeps its single round
+    /// trip and a large batch counts against the breaker once. A backend failure for any of the
+    /// ids fails, and retries, the whole batch.
+    async fn retrieve_many(&self, ids: &[usize]) -> Vec<Result<Data, DataRepoError>> {
+        let batch = self
+            .retried(|| async move {
+                let results = self.inner.retrieve_many(ids).await;
+                let failure = results
+                    .iter()
+                    .find_map(|result| result.as_ref().err().filter(|err| is_backend_failure(err)))
+                    .cloned();
+
+                match failure {
+                    Some(err) => Err(err),
+                    None => Ok(results),
+                }
+            })
+            .await;
+
+        match batch {
+            Ok(results) => results,
+            Err(err) => ids.iter().map(|_| Err(err.clone())).collect(),
+        }
+    }
+
+    async fn update(&self, id: usize, data: Data, expected: Option<Revision>) -> Result<Data, DataRepoError> {
+        self.guarded(self.inner.update(id, data, expected)).await
+    }
+
+    async fn delete(&self, id: usize) -> Result<(), DataRepoError> {
+        self.guarded(self.inner.delete(id)).await
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<Page<Data>, DataRepoError> {
+        self.retried(|| self.inner.list(query)).await
+    }
+
+    async fn health_check(&self) -> HealthStatus {
+        if self.breaker.is_open() {
+            return HealthStatus::unhealthy("circuit breaker is open");
+        }
+
+        self.inner.health_check().await
+    }
+
+    async fn shutdown(&self) {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    fn config() -> ResilienceConfig {
+        ResilienceConfig {
+            enabled: true,
+            call_timeout_ms: 100,
+            max_retries: 2,
+            backoff_base_ms: 10,
+            backoff_max_ms: 50,
+            breaker_failure_threshold: 3,
+            breaker_open_secs: 5,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_calls_time_out() {
+        let inner = MockDataRepo::new().reply(Reply::ok(data(1)).after(Duration::from_secs(60)));
+        let repo = ResilientDataRepo::new(inner.clone(), &config());
+
+        let started = Instant::now();
+        assert!(matches!(repo.update(1, data(1), None).await, Err(DataRepoError::Timeout)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        inner.assert_call_count(1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reads_are_retried_on_retryable_errors() {
+        let inner = MockDataRepo::new()
+            .reply(Reply::err(DataRepoError::Unavailable))
+            .reply(Reply::ok(data(1)).after(Duration::from_secs(60)))
+            .reply(Reply::ok(data(1)));
+        let repo = ResilientDataRepo::new(inner.clone(), &config());
+
+        assert_eq!(repo.retrieve(1).await.unwrap().id, 1);
+        inner.assert_call_count(3);
+
+        // Errors that another attempt won't fix are returned as they are
+        let inner = MockDataRepo::new();
+        let repo = ResilientDataRepo::new(inner.clone(), &config());
+        assert!(matches!(repo.retrieve(1).await, Err(DataRepoError::NotFound)));
+        inner.assert_call_count(1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retrieve_many_is_one_guarded_call() {
+        let inner = MockDataRepo::new()
+            .reply_for(1, Reply::ok(data(1)))
+            .reply_for(2, Reply::err(DataRepoError::Unavailable))
+            .reply_for(2, Reply::ok(data(2)));
+        let repo = ResilientDataRepo::new(inner.clone(), &config());
+
+        // The backend failed for 2 on the first attempt, so the whole batch was retried
+        let results = repo.retrieve_many(&[1, 2, 3]).await;
+        assert_eq!(results[0].as_ref().unwrap().id, 1);
+        assert_eq!(results[1].as_ref().unwrap().id, 2);
+        assert!(matches!(results[2], Err(DataRepoError::NotFound)));
+        inner.assert_call_count(6);
+
+        // However many ids fail, a batch is a single failure as far as the breaker is concerned
+        let inner = MockDataRepo::new().reply(Reply::err(DataRepoError::Unavailable));
+        let config = ResilienceConfig {
+            max_retries: 0,
+            ..config()
+        };
+        let repo = ResilientDataRepo::new(inner.clone(), &config);
+
+        let results = repo.retrieve_many(&[1, 2, 3, 4, 5]).await;
+        assert!(results.iter().all(|result| matches!(result, Err(DataRepoError::Unavailable))));
+        assert!(repo.health_check().await.is_healthy());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_writes_are_not_retried() {
+        let inner = MockDataRepo::new().reply(Reply::err(DataRepoError::Unavailable));
+        let repo = ResilientDataRepo::new(inner.clone(), &config());
+
+        assert!(matches!(repo.create(data(1)).await, Err(DataRepoError::Unavailable)));
+        inner.assert_call_count(1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_breaker_opens_and_recovers() {
+        let inner = MockDataRepo::new()
+            .reply(Reply::err(DataRepoError::Unavailable))
+            .reply(Reply::err(DataRepoError::Unavailable))
+            .reply(Reply::err(DataRepoError::Unavailable))
+            .reply(Reply::ok(data(1)));
+        let repo = ResilientDataRepo::new(inner.clone(), &config());
+
+        for _ in 0..3 {
+            assert!(repo.delete(1).await.is_err());
+        }
+        assert!(!repo.health_check().await.is_healthy());
+
+        // Open: calls fail fast without reaching the backend
+        assert!(matches!(repo.retrieve(1).await, Err(DataRepoError::Unavailable)));
+        inner.assert_call_count(3);
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+
+        // The probe succeeds and closes the breaker again
+        assert_eq!(repo.retrieve(1).await.unwrap().id, 1);
+        assert!(repo.health_check().await.is_healthy());
+        inner.assert_call_count(4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_an_abandoned_probe_reopens_the_breaker() {
+        let inner = MockDataRepo::new()
+            .reply(Reply::err(DataRepoError::Unavailable))
+            .reply(Reply::err(DataRepoError::Unavailable))
+            .reply(Reply::err(DataRepoError::Unavailable))
+            .reply(Reply::ok(data(1)).after(Duration::from_millis(50)))
+            .reply(Reply::ok(data(1)));
+        let repo = ResilientDataRepo::new(inner.clone(), &config());
+
+        for _ in 0..3 {
+            assert!(repo.delete(1).await.is_err());
+        }
+        tokio::time::advance(Duration::from_secs(5)).await;
+
+        // The probe's caller gives up before it finishes
+        let abandoned = tokio::time::timeout(Duration::from_millis(10), repo.retrieve(1)).await;
+        assert!(abandoned.is_err());
+        inner.assert_call_count(4);
+
+        // The breaker is open again rather than waiting on the abandoned probe
+        assert!(matches!(repo.retrieve(1).await, Err(DataRepoError::Unavailable)));
+        inner.assert_call_count(4);
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(repo.retrieve(1).await.unwrap().id, 1);
+        assert!(repo.health_check().await.is_healthy());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_calls_started_before_the_breaker_opened_dont_close_it() {
+        let inner = MockDataRepo::new()
+            .reply_for(1, Reply::ok(data(1)).after(Duration::from_millis(50)))
+            .reply_for(2, Reply::err(DataRepoError::Unavailable));
+        let repo = ResilientDataRepo::new(inner.clone(), &config());
+
+        let (slow, ()) = tokio::join!(repo.retrieve(1), async {
+            for _ in 0..3 {
+                assert!(repo.delete(2).await.is_err());
+            }
+        });
+
+        // The slow call succeeded after the breaker opened, which doesn't make the backend healthy
+        assert_eq!(slow.unwrap().id, 1);
+        assert!(!repo.health_check().await.is_healthy());
+        assert!(matches!(repo.retrieve(1).await, Err(DataRepoError::Unavailable)));
+        inner.assert_call_count(4);
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let repo = ResilientDataRepo::new(MockDataRepo::new(), &config());
+
+        for attempt in 0..40 {
+            assert!(repo.backoff(attempt) <= Duration::from_millis(50));
+        }
+    }
+}
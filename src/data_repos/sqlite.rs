@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use axum::async_trait;
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::QueryBuilder;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::health::HealthStatus;
+use crate::listing::{ListQuery, NameFilter, Page, SortOrder};
+use crate::{Data, DataRepo, DataRepoError, Revision};
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// A [`DataRepo`] persisted in a SQLite database. The URL can point either at a file on disk
+/// (`sqlite://data.db`) or at an in-memory database (`sqlite::memory:`).
+pub(crate) struct SqliteDataRepo {
+    pool: SqlitePool,
+}
+
+impl SqliteDataRepo {
+    pub(crate) async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+
+        // An in-memory database only lives as long as a connection to it is open, so pooled
+        // connections are never retired.
+        let pool = SqlitePoolOptions::new()
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?;
+
+        Ok(Self { pool })
+    }
+
+    /// Brings the schema up to date using the migrations embedded from `migrations/`.
+    pub(crate) async fn migrate(&self) -> Result<(), MigrateError> {
+        MIGRATOR.run(&self.pool).await
+    }
+}
+
+fn to_db_id(id: usize) -> Result<i64, DataRepoError> {
+    i64::try_from(id).map_err(|_| DataRepoError::InvalidRequest)
+}
+
+/// The columns of the `data` table, in the order [`DataRow`] reads them.
+const DATA_COLUMNS: &str = "id, name, tags, payload, created_at, updated_at, version";
+
+/// Tags and payloads are stored as JSON text and timestamps as RFC 3339 text.
+type DataRow = (i64, String, String, String, String, String, i64);
+
+fn to_db_timestamp(timestamp: OffsetDateTime) -> Result<String, DataRepoError> {
+    timestamp.format(&Rfc3339).map_err(DataRepoError::internal)
+}
+
+fn to_db_json(value: &impl serde::Serialize) -> Result<String, DataRepoError> {
+    serde_json::to_string(value).map_err(DataRepoError::internal)
+}
+
+fn from_db_row((id, name, tags, payload, created_at, updated_at, version): DataRow) -> Result<Data, DataRepoError> {
+    Ok(Data {
+        id: usize::try_from(id).map_err(DataRepoError::internal)?,
+        name,
+        tags: serde_json::from_str(&tags).map_err(DataRepoError::internal)?,
+        payload: serde_json::from_str(&payload).map_err(DataRepoError::internal)?,
+        created_at: OffsetDateTime::parse(&created_at, &Rfc3339).map_err(DataRepoError::internal)?,
+        updated_at: OffsetDateTime::parse(&updated_at, &Rfc3339).map_err(DataRepoError::internal)?,
+        version: u64::try_from(version).map_err(DataRepoError::internal)?,
+    })
+}
+
+impl From<sqlx::Error> for DataRepoError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => DataRepoError::Conflict,
+            sqlx::Error::PoolTimedOut => DataRepoError::Timeout,
+            sqlx::Error::PoolClosed | sqlx::Error::Io(_) => DataRepoError::Unavailable,
+            err => DataRepoError::internal(err),
+        }
+    }
+}
+
+#[async_trait]
+impl DataRepo for SqliteDataRepo {
+    async fn create(&self, data: Data) -> Result<Data, DataRepoError> {
+        sqlx::query(&format!("INSERT INTO data ({DATA_COLUMNS}) VALUES (?, ?, ?, ?, ?, ?, ?)"))
+            .bind(to_db_id(data.id)?)
+            .bind(&data.name)
+            .bind(to_db_json(&data.tags)?)
+            .bind(to_db_json(&data.payload)?)
+            .bind(to_db_timestamp(data.created_at)?)
+            .bind(to_db_timestamp(data.updated_at)?)
+            .bind(i64::try_from(data.version).map_err(|_| DataRepoError::InvalidRequest)?)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(data)
+    }
+
+    async fn retrieve(&self, id: usize) -> Result<Data, DataRepoError> {
+        let row: Option<DataRow> = sqlx::query_as(&format!("SELECT {DATA_COLUMNS} FROM data WHERE id = ?"))
+            .bind(to_db_id(id)?)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.ok_or(DataRepoError::NotFound).and_then(from_db_row)
+    }
+
+    async fn retrieve_many(&self, ids: &[usize]) -> Vec<Result<Data, DataRepoError>> {
+        let db_ids: Vec<i64> = ids.iter().filter_map(|&id| to_db_id(id).ok()).collect();
+        if db_ids.is_empty() {
+            return ids.iter().map(|_| Err(DataRepoError::InvalidRequest)).collect();
+        }
+
+        let fetched = async {
+            let mut sql = QueryBuilder::<Sqlite>::new(format!("SELECT {DATA_COLUMNS} FROM data WHERE id IN ("));
+            let mut separated = sql.separated(", ");
+            for db_id in db_ids {
+                separated.push_bind(db_id);
+            }
+            sql.push(")");
+
+            let rows: Vec<DataRow> = sql.build_query_as().fetch_all(&self.pool).await?;
+            rows.into_iter()
+                .map(|row| from_db_row(row).map(|data| (data.id, data)))
+                .collect::<Result<HashMap<_, _>, DataRepoError>>()
+        };
+
+        match fetched.await {
+            Ok(found) => ids
+                .iter()
+                .map(|&id| {
+                    to_db_id(id)?;
+                    found.get(&id).cloned().ok_or(DataRepoError::NotFound)
+                })
+                .collect(),
+            Err(err) => ids.iter().map(|_| Err(err.clone())).collect(),
+        }
+    }
+
+    async fn update(&self, id: usize, data: Data, expected: Option<Revision>) -> Result<Data, DataRepoError> {
+        let (expected_version, expected_created_at) = match expected {
+            Some(expected) => (
+                Some(i64::try_from(expected.version).map_err(|_| DataRepoError::PreconditionFailed)?),
+                Some(to_db_timestamp(expected.created_at)?),
+            ),
+            None => (None, None),
+        };
+
+        // The revision check is part of the UPDATE itself, so a concurrent writer can't slip in
+        // between checking and writing
+        let row: Option<DataRow> = sqlx::query_as(&format!(
+            "UPDATE data SET name = ?, tags = ?, payload = ?, updated_at = ?, version = version + 1 \
+             WHERE id = ? AND (? IS NULL OR (version = ? AND created_at = ?)) RETURNING {DATA_COLUMNS}"
+        ))
+        .bind(&data.name)
+        .bind(to_db_json(&data.tags)?)
+        .bind(to_db_json(&data.payload)?)
+        .bind(to_db_timestamp(data.updated_at)?)
+        .bind(to_db_id(id)?)
+        .bind(expected_version)
+        .bind(expected_version)
+        .bind(expected_created_at)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => from_db_row(row),
+            None if expected_version.is_some() => match self.retrieve(id).await {
+                Ok(_) => Err(DataRepoError::PreconditionFailed),
+                Err(err) => Err(err),
+            },
+            None => Err(DataRepoError::NotFound),
+        }
+    }
+
+    async fn delete(&self, id: usize) -> Result<(), DataRepoError> {
+        let result = sqlx::query("DELETE FROM data WHERE id = ?")
+            .bind(to_db_id(id)?)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DataRepoError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<Page<Data>, DataRepoError> {
+        let mut sql = QueryBuilder::<Sqlite>::new(format!("SELECT {DATA_COLUMNS} FROM data WHERE 1 = 1"));
+
+        if let Some(min_id) = query.filter.min_id {
+            sql.push(" AND id >= ").push_bind(to_db_id(min_id)?);
+        }
+
+        if let Some(max_id) = query.filter.max_id {
+            sql.push(" AND id <= ").push_bind(to_db_id(max_id)?);
+        }
+
+        match &query.filter.name {
+            Some(NameFilter::Exact(name)) => {
+                sql.push(" AND name = ").push_bind(name.clone());
+            }
+            // Compared with substr rather than LIKE, which would treat `%` and `_` in the prefix
+            // as wildcards and ignore case
+            Some(NameFilter::Prefix(prefix)) => {
+                sql.push(" AND substr(name, 1, length(")
+                    .push_bind(prefix.clone())
+                    .push(")) = ")
+                    .push_bind(prefix.clone());
+            }
+            None => {}
+        }
+
+        if let Some(tag) = &query.filter.tag {
+            sql.push(" AND EXISTS (SELECT 1 FROM json_each(data.tags) WHERE json_each.value = ")
+                .push_bind(tag.clone())
+                .push(")");
+        }
+
+        // Ids are unique, so ordering by them alone is already stable
+        let (after_cursor, order) = match query.sort {
+            SortOrder::IdAscending => (" AND id > ", " ORDER BY id ASC"),
+            SortOrder::IdDescending => (" AND id < ", " ORDER BY id DESC"),
+        };
+
+        if let Some(cursor) = query.cursor {
+            sql.push(after_cursor).push_bind(to_db_id(cursor.after())?);
+        }
+
+        // One extra row tells us whether there is another page
+        sql.push(order).push(" LIMIT ").push_bind(to_db_id(query.limit + 1)?);
+
+        let rows: Vec<DataRow> = sql.build_query_as().fetch_all(&self.pool).await?;
+        let items = rows.into_iter().map(from_db_row).collect::<Result<_, _>>()?;
+
+        Ok(query.page(items))
+    }
+
+    async fn health_check(&self) -> HealthStatus {
+        match sqlx::query("SELECT 1").execute(&self.pool).await {
+            Ok(_) => HealthStatus::Healthy,
+            Err(err) => HealthStatus::unhealthy(err.to_string()),
+        }
+    }
+
+    async fn shutdown(&self) {
+        self.pool.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listing::DataFilter;
+    use crate::test_helpers::data;
+    use crate::DataInput;
+
+    async fn migrated_repo(url: &str) -> SqliteDataRepo {
+        let repo = SqliteDataRepo::connect(url).await.expect("sqlite database to open");
+        repo.migrate().await.expect("migrations to apply");
+        repo
+    }
+
+    #[tokio::test]
+    async fn test_crud_against_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("data.db").display());
+
+        let repo = migrated_repo(&url).await;
+
+        assert_eq!(repo.create(data(3)).await.unwrap().id, 3);
+        assert_eq!(repo.create(data(1)).await.unwrap().id, 1);
+        assert!(matches!(repo.create(data(1)).await, Err(DataRepoError::Conflict)));
+
+        assert_eq!(repo.retrieve(3).await.unwrap().id, 3);
+        assert!(matches!(repo.retrieve(2).await, Err(DataRepoError::NotFound)));
+
+        assert_eq!(repo.update(3, data(3), None).await.unwrap().id, 3);
+        assert!(matches!(
+            repo.update(2, data(2), None).await,
+            Err(DataRepoError::NotFound)
+        ));
+
+        let page = repo.list(&ListQuery::default()).await.unwrap();
+        let ids: Vec<usize> = page.items.into_iter().map(|data| data.id).collect();
+        assert_eq!(ids, vec![1, 3]);
+
+        assert!(repo.delete(3).await.is_ok());
+        assert!(matches!(repo.delete(3).await, Err(DataRepoError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_fields_round_trip() {
+        let repo = migrated_repo("sqlite::memory:").await;
+
+        let mut input = DataInput::new("widget");
+        input.tags = vec!["blue".to_string(), "large".to_string()];
+        input.payload = serde_json::json!({"weight": 12.5});
+
+        let created = repo.create(Data::new(1, input)).await.unwrap();
+        assert_eq!(repo.retrieve(1).await.unwrap(), created);
+
+        let updated = repo.update(1, data(1), None).await.unwrap();
+        assert_eq!(updated.name, "record 1");
+        assert!(updated.tags.is_empty());
+        assert_eq!(updated.created_at, created.created_at);
+        assert_eq!(updated.version, 2);
+        assert_eq!(repo.retrieve(1).await.unwrap(), updated);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_many_in_one_query() {
+        let repo = migrated_repo("sqlite::memory:").await;
+        repo.create(data(1)).await.unwrap();
+        repo.create(data(2)).await.unwrap();
+
+        let results = repo.retrieve_many(&[2, 3, 1, 2]).await;
+        let ids: Vec<Option<usize>> = results.iter().map(|result| result.as_ref().ok().map(Data::id)).collect();
+        assert_eq!(ids, vec![Some(2), None, Some(1), Some(2)]);
+        assert!(matches!(results[1], Err(DataRepoError::NotFound)));
+
+        assert!(repo.retrieve_many(&[]).await.is_empty());
+        assert!(matches!(
+            repo.retrieve_many(&[usize::MAX]).await[0],
+            Err(DataRepoError::InvalidRequest)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_update_checks_the_expected_revision() {
+        let repo = migrated_repo("sqlite::memory:").await;
+        let first = repo.create(data(1)).await.unwrap().revision();
+        let later = Revision { version: 2, ..first };
+
+        assert!(matches!(
+            repo.update(1, data(1), Some(later)).await,
+            Err(DataRepoError::PreconditionFailed)
+        ));
+        assert_eq!(repo.update(1, data(1), Some(first)).await.unwrap().version, 2);
+        assert!(matches!(
+            repo.update(1, data(1), Some(first)).await,
+            Err(DataRepoError::PreconditionFailed)
+        ));
+        assert!(matches!(
+            repo.update(2, data(2), Some(first)).await,
+            Err(DataRepoError::NotFound)
+        ));
+
+        // A record deleted and created again is back at version 1, but isn't the one `first` saw
+        repo.delete(1).await.unwrap();
+        let recreated = Data {
+            created_at: first.created_at + time::Duration::seconds(1),
+            ..data(1)
+        };
+        repo.create(recreated).await.unwrap();
+        assert!(matches!(
+            repo.update(1, data(1), Some(first)).await,
+            Err(DataRepoError::PreconditionFailed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_pages_in_the_database() {
+        let repo = migrated_repo("sqlite::memory:").await;
+        for id in 1..=5 {
+            repo.create(data(id)).await.unwrap();
+        }
+
+        let mut query = ListQuery {
+            filter: DataFilter {
+                max_id: Some(4),
+                ..DataFilter::default()
+            },
+            limit: 2,
+            sort: SortOrder::IdDescending,
+            ..ListQuery::default()
+        };
+
+        let mut ids = Vec::new();
+        loop {
+            let page = repo.list(&query).await.unwrap();
+            ids.extend(page.items.iter().map(|data| data.id));
+
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+
+        assert_eq!(ids, vec![4, 3, 2, 1]);
+    }
+
+    async fn listed_ids(repo: &SqliteDataRepo, filter: DataFilter) -> Vec<usize> {
+        let query = ListQuery {
+            filter,
+            ..ListQuery::default()
+        };
+        let page = repo.list(&query).await.unwrap();
+        page.items.iter().map(|data| data.id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_filters_on_name_and_tags_in_the_database() {
+        let repo = migrated_repo("sqlite::memory:").await;
+        let records = [
+            (1, "apple", vec!["red"]),
+            (2, "apricot", vec!["orange", "red"]),
+            (3, "a%le", vec![]),
+            (4, "banana", vec!["yellow"]),
+        ];
+        for (id, name, tags) in records {
+            let mut input = DataInput::new(name);
+            input.tags = tags.into_iter().map(String::from).collect();
+            repo.create(Data::new(id, input)).await.unwrap();
+        }
+
+        let exact = DataFilter {
+            name: Some(NameFilter::Exact("apple".to_string())),
+            ..DataFilter::default()
+        };
+        assert_eq!(listed_ids(&repo, exact).await, vec![1]);
+
+        let prefix = DataFilter {
+            name: Some(NameFilter::Prefix("ap".to_string())),
+            ..DataFilter::default()
+        };
+        assert_eq!(listed_ids(&repo, prefix).await, vec![1, 2]);
+
+        // Neither a wildcard nor case-insensitive
+        let literal = DataFilter {
+            name: Some(NameFilter::Prefix("A%".to_string())),
+            ..DataFilter::default()
+        };
+        assert_eq!(listed_ids(&repo, literal).await, Vec::<usize>::new());
+
+        let tagged = DataFilter {
+            tag: Some("red".to_string()),
+            ..DataFilter::default()
+        };
+        assert_eq!(listed_ids(&repo, tagged).await, vec![1, 2]);
+
+        let both = DataFilter {
+            name: Some(NameFilter::Prefix("a".to_string())),
+            tag: Some("orange".to_string()),
+            ..DataFilter::default()
+        };
+        assert_eq!(listed_ids(&repo, both).await, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_records_survive_reconnecting() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite://{}", dir.path().join("data.db").display());
+
+        let repo = migrated_repo(&url).await;
+        repo.create(data(5)).await.unwrap();
+        drop(repo);
+
+        let repo = migrated_repo(&url).await;
+        assert_eq!(repo.retrieve(5).await.unwrap().id, 5);
+    }
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let repo = migrated_repo("sqlite::memory:").await;
+        assert!(repo.health_check().await.is_healthy());
+
+        repo.shutdown().await;
+        assert!(!repo.health_check().await.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_database() {
+        let repo = migrated_repo("sqlite::memory:").await;
+
+        repo.create(data(9)).await.unwrap();
+        assert_eq!(repo.retrieve(9).await.unwrap().id, 9);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+
+use axum::extract::State;
+use axum::Json;
+use http::StatusCode;
+use serde::Serialize;
+
+use crate::AppState;
+
+/// Upper bound on how long a single dependency may take to answer a health check before it is
+/// reported as unhealthy.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum HealthStatus {
+    Healthy,
+    Unhealthy { reason: String },
+}
+
+impl HealthStatus {
+    pub fn unhealthy(reason: impl Into<String>) -> Self {
+        HealthStatus::Unhealthy { reason: reason.into() }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, HealthStatus::Healthy)
+    }
+}
+
+/// Runs a single dependency health check, treating a check that hangs as unhealthy.
+pub(crate) async fn check(health_check: impl Future<Output = HealthStatus>) -> HealthStatus {
+    tokio::time::timeout(CHECK_TIMEOUT, health_check)
+        .await
+        .unwrap_or_else(|_| HealthStatus::unhealthy("health check timed out"))
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    status: &'static str,
+    checks: BTreeMap<&'static str, HealthStatus>,
+}
+
+/// Liveness probe, answers as long as the process is able to serve requests at all.
+pub async fn healthz_handler() -> Json<serde_json::Value> {
+    Json(serde_json::json!({"status": "alive"}))
+}
+
+/// Readiness probe, only reports ready when every dependency in [`AppState`] is healthy.
+pub async fn readyz_handler(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let checks = state.health_checks().await;
+
+    if checks.values().all(HealthStatus::is_healthy) {
+        (StatusCode::OK, Json(Readiness { status: "ready", checks }))
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(Readiness { status: "not_ready", checks }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idempotency::{IdempotencyEntry, IdempotencyStore, StoredResponse};
+    use crate::test_helpers::*;
+
+    use std::sync::Arc;
+
+    use axum::async_trait;
+
+    fn health_app(status: HealthStatus) -> TestClient {
+        TestApp::builder().with_data_repo(MockDataRepo::new().with_health(status)).build()
+    }
+
+    #[tokio::test]
+    async fn test_ready_when_dependencies_are_healthy() {
+        let client = health_app(HealthStatus::Healthy);
+
+        let res = client.get("/readyz").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: serde_json::Value = res.json().await;
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["checks"]["data_repo"]["status"], "healthy");
+    }
+
+    #[tokio::test]
+    async fn test_not_ready_when_a_dependency_is_unhealthy() {
+        let client = health_app(HealthStatus::unhealthy("connection refused"));
+
+        let res = client.get("/readyz").send().await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body: serde_json::Value = res.json().await;
+        assert_eq!(body["status"], "not_ready");
+        assert_eq!(body["checks"]["data_repo"]["status"], "unhealthy");
+        assert_eq!(body["checks"]["data_repo"]["reason"], "connection refused");
+
+        // Liveness doesn't depend on anything injected
+        let res = client.get("/healthz").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    struct OfflineStore;
+
+    #[async_trait]
+    impl IdempotencyStore for OfflineStore {
+        async fn reserve(&self, _key: &str, _fingerprint: &str) -> Option<IdempotencyEntry> {
+            None
+        }
+
+        async fn complete(&self, _key: &str, _response: StoredResponse) {}
+
+        async fn release(&self, _key: &str) {}
+
+        async fn health_check(&self) -> HealthStatus {
+            HealthStatus::unhealthy("store offline")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_every_injected_dependency_is_checked() {
+        let client = TestApp::builder()
+            .with_service::<dyn IdempotencyStore + Send + Sync>(Arc::new(OfflineStore))
+            .build();
+
+        let res = client.get("/readyz").send().await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body: serde_json::Value = res.json().await;
+        assert_eq!(body["checks"]["data_repo"]["status"], "healthy");
+        assert_eq!(body["checks"]["idempotency_store"]["reason"], "store offline");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_hung_checks_are_unhealthy() {
+        let status = check(std::future::pending()).await;
+        assert_eq!(status, HealthStatus::unhealthy("health check timed out"));
+    }
+}
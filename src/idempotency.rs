@@ -0,0 +1,570 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::async_trait;
+use axum::body::{self, Body, Bytes, Full, HttpBody};
+use axum::extract::{FromRef, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use bytes::BytesMut;
+use http::header::AUTHORIZATION;
+use http::request::Parts;
+use http::{HeaderMap, HeaderValue, Method, Request, StatusCode};
+use sha2::{Digest, Sha256};
+
+use crate::authz::{Authorized, Write};
+use crate::container::Container;
+use crate::health::HealthStatus;
+use crate::problem::Problem;
+
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Set on a response that was replayed from the store rather than produced by the handler.
+pub const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+
+const MAX_KEY_LEN: usize = 255;
+
+/// How long the in-memory store remembers a key when none is given.
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A response kept so that a retry with the same `Idempotency-Key` gets the same answer.
+#[derive(Clone, Debug)]
+pub struct StoredResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl IntoResponse for StoredResponse {
+    fn into_response(self) -> Response {
+        let mut response = Response::new(body::boxed(Full::from(self.body)));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+            .headers_mut()
+            .insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+
+        response
+    }
+}
+
+/// What a store knows about a key: the request that claimed it and, once that request has
+/// finished, its response.
+#[derive(Clone, Debug)]
+pub struct IdempotencyEntry {
+    /// Identifies the caller, method, target and body of the request that claimed the key.
+    pub fingerprint: String,
+    /// `None` while the request that claimed the key is still being handled.
+    pub response: Option<StoredResponse>,
+}
+
+#[async_trait]
+pub trait IdempotencyStore {
+    /// Claims `key` for a request with `fingerprint`. Returns `None` if the key was free, in which
+    /// case the caller must later either [`complete`](Self::complete) or
+    /// [`release`](Self::release) it, and the entry already holding the key otherwise.
+    async fn reserve(&self, key: &str, fingerprint: &str) -> Option<IdempotencyEntry>;
+
+    /// Records the response to the request that reserved `key`.
+    async fn complete(&self, key: &str, response: StoredResponse);
+
+    /// Frees a reserved `key` without recording a response, so the request can be retried.
+    async fn release(&self, key: &str);
+
+    async fn health_check(&self) -> HealthStatus {
+        HealthStatus::Healthy
+    }
+}
+
+pub type DynIdempotencyStore = Arc<dyn IdempotencyStore + Send + Sync>;
+
+/// An [`IdempotencyStore`] held in process memory. Keys are forgotten once they are older than
+/// the configured time to live, and on restart.
+pub struct InMemoryIdempotencyStore {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, IdempotencyEntry)>>,
+}
+
+impl InMemoryIdempotencyStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::default(),
+        }
+    }
+}
+
+impl Default for InMemoryIdempotencyStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL)
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for InMemoryIdempotencyStore {
+    async fn reserve(&self, key: &str, fingerprint: &str) -> Option<IdempotencyEntry> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (reserved_at, _)| reserved_at.elapsed() < self.ttl);
+
+        if let Some((_, entry)) = entries.get(key) {
+            return Some(entry.clone());
+        }
+
+        let entry = IdempotencyEntry {
+            fingerprint: fingerprint.to_string(),
+            response: None,
+        };
+        entries.insert(key.to_string(), (Instant::now(), entry));
+
+        None
+    }
+
+    async fn complete(&self, key: &str, response: StoredResponse) {
+        if let Some((_, entry)) = self.entries.lock().unwrap().get_mut(key) {
+            entry.response = Some(response);
+        }
+    }
+
+    async fn release(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+/// State for the [`idempotency`] middleware.
+#[derive(Clone)]
+pub(crate) struct IdempotencyState {
+    store: DynIdempotencyStore,
+    /// Authorizes the caller before a key is reserved for it.
+    container: Arc<Container>,
+    /// Request bodies are buffered to fingerprint them, so they are held to the same limit the
+    /// extractors enforce.
+    body_limit: usize,
+}
+
+impl IdempotencyState {
+    pub(crate) fn new(store: DynIdempotencyStore, container: Arc<Container>, body_limit: usize) -> Self {
+        Self {
+            store,
+            container,
+            body_limit,
+        }
+    }
+}
+
+impl FromRef<IdempotencyState> for Arc<Container> {
+    fn from_ref(state: &IdempotencyState) -> Self {
+        state.container.clone()
+    }
+}
+
+/// Holds a reserved key, releasing it if the request fails or is dropped before a response is
+/// stored.
+struct Reservation {
+    store: DynIdempotencyStore,
+    key: String,
+    completed: bool,
+}
+
+impl Reservation {
+    async fn complete(mut self, response: StoredResponse) {
+        self.store.complete(&self.key, response).await;
+        self.completed = true;
+    }
+
+    /// Frees the key before the response goes out, so an immediate retry finds it free.
+    async fn release(mut self) {
+        self.store.release(&self.key).await;
+        self.completed = true;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if !self.completed {
+            let store = self.store.clone();
+            let key = std::mem::take(&mut self.key);
+            tokio::spawn(async move { store.release(&key).await });
+        }
+    }
+}
+
+/// Makes POST and PATCH requests carrying an `Idempotency-Key` safe to retry. The first request
+/// with a key runs as usual and its response is stored; a retry with the same key and the same
+/// request is answered from the store without reaching the handler.
+///
+/// Keys are scoped to the caller's `Authorization` header, so a caller can never be answered with
+/// a response stored for somebody else's request. Callers without the [`Write`] permission every
+/// idempotent route needs are turned away before a key is reserved, so they can't hold one.
+///
+/// Reusing a key for a different request is rejected with 422, and a retry that arrives while the
+/// original is still being handled is rejected with 409. Only successes and the handler's own
+/// rejections of the request (400 and 422) are stored, see [`is_storable`].
+pub(crate) async fn idempotency(
+    State(state): State<IdempotencyState>,
+    _: Authorized<Write>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if !matches!(*request.method(), Method::POST | Method::PATCH) {
+        return next.run(request).await;
+    }
+
+    let key = match request.headers().get(IDEMPOTENCY_KEY).map(parse_key) {
+        None => return next.run(request).await,
+        Some(Ok(key)) => key,
+        Some(Err(problem)) => return problem.into_response(),
+    };
+
+    let (parts, body) = request.into_parts();
+    let body = match collect(body, state.body_limit).await {
+        Ok(body) => body,
+        Err(problem) => return problem.into_response(),
+    };
+
+    let caller = caller(&parts);
+    let key = format!("{caller}:{key}");
+    let fingerprint = fingerprint(&[
+        caller.as_bytes(),
+        parts.method.as_str().as_bytes(),
+        parts.uri.to_string().as_bytes(),
+        &body[..],
+    ]);
+
+    match state.store.reserve(&key, &fingerprint).await {
+        None => {}
+        Some(entry) if entry.fingerprint != fingerprint => {
+            return Problem::new(StatusCode::UNPROCESSABLE_ENTITY)
+                .with_detail("the Idempotency-Key has already been used for a different request")
+                .into_response()
+        }
+        Some(IdempotencyEntry {
+            response: Some(response),
+            ..
+        }) => return response.into_response(),
+        Some(_) => {
+            return Problem::new(StatusCode::CONFLICT)
+                .with_detail("a request with this Idempotency-Key is still being processed")
+                .into_response()
+        }
+    }
+
+    let reservation = Reservation {
+        store: state.store.clone(),
+        key,
+        completed: false,
+    };
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if !is_storable(response.status()) {
+        reservation.release().await;
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match collect(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(_) => {
+            reservation.release().await;
+            return Problem::new(StatusCode::INTERNAL_SERVER_ERROR).into_response();
+        }
+    };
+
+    reservation
+        .complete(StoredResponse {
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        })
+        .await;
+
+    Response::from_parts(parts, body::boxed(Full::from(body)))
+}
+
+fn parse_key(value: &HeaderValue) -> Result<String, Problem> {
+    match value.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => Ok(key.to_string()),
+        _ => Err(Problem::new(StatusCode::BAD_REQUEST)
+            .with_detail(format!("Idempotency-Key must be 1 to {MAX_KEY_LEN} visible ASCII characters"))),
+    }
+}
+
+/// Whether a response is worth replaying to a retry. Server errors may go away, and failed
+/// authentication, authorization or preconditions may succeed once the client fixes its
+/// credentials or refetches the record, so only a success or a rejection of the request itself
+/// is final.
+fn is_storable(status: StatusCode) -> bool {
+    status.is_success() || status == StatusCode::BAD_REQUEST || status == StatusCode::UNPROCESSABLE_ENTITY
+}
+
+/// A digest of the credentials a request was made with, so the tokens themselves never end up in
+/// the store.
+fn caller(parts: &Parts) -> String {
+    let credentials = parts.headers.get(AUTHORIZATION).map(HeaderValue::as_bytes).unwrap_or_default();
+    format!("{:x}", Sha256::digest(credentials))
+}
+
+fn fingerprint(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        // Length prefixes keep the boundaries between the parts unambiguous
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+async fn collect<B>(mut body: B, limit: usize) -> Result<Bytes, Problem>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+    B::Error: std::fmt::Display,
+{
+    let mut buffer = BytesMut::new();
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|err| Problem::new(StatusCode::BAD_REQUEST).with_detail(err.to_string()))?;
+
+        if buffer.len() + chunk.len() > limit {
+            return Err(Problem::new(StatusCode::PAYLOAD_TOO_LARGE));
+        }
+
+        buffer.extend_from_slice(&chunk);
+    }
+
+    Ok(buffer.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::User;
+    use crate::authz::ScopePolicy;
+    use crate::test_helpers::*;
+    use crate::DataRepoError;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn app(repo: MockDataRepo) -> TestClient {
+        TestApp::builder().with_data_repo(repo).build()
+    }
+
+    #[tokio::test]
+    async fn test_retries_are_replayed() {
+        let repo = MockDataRepo::new().reply(Reply::ok(data(5)));
+        let client = app(repo.clone());
+
+        let first = client.post("/data").header(IDEMPOTENCY_KEY, "abc").json(&data_body(5)).send().await;
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert!(first.headers().get(IDEMPOTENT_REPLAYED).is_none());
+        let etag = first.headers()["etag"].clone();
+        let first_body = first.text().await;
+
+        let retry = client.post("/data").header(IDEMPOTENCY_KEY, "abc").json(&data_body(5)).send().await;
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(retry.headers()[IDEMPOTENT_REPLAYED], "true");
+        assert_eq!(retry.headers()["etag"], etag);
+        assert_eq!(retry.text().await, first_body);
+
+        repo.assert_call_count(1);
+
+        // Requests without a key are never deduplicated
+        client.post("/data").json(&data_body(5)).send().await;
+        client.post("/data").json(&data_body(5)).send().await;
+        repo.assert_call_count(3);
+    }
+
+    #[tokio::test]
+    async fn test_reusing_a_key_for_another_request_is_rejected() {
+        let repo = MockDataRepo::new().reply(Reply::ok(data(5)));
+        let client = app(repo.clone());
+
+        client.post("/data").header(IDEMPOTENCY_KEY, "abc").json(&data_body(5)).send().await;
+
+        let res = client.post("/data").header(IDEMPOTENCY_KEY, "abc").json(&data_body(6)).send().await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(res.headers()["content-type"], crate::problem::PROBLEM_JSON);
+
+        let res = client
+            .patch("/data/5")
+            .header(IDEMPOTENCY_KEY, "abc")
+            .json(&data_body(5))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        repo.assert_call_count(1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_retries_are_rejected() {
+        let repo = MockDataRepo::new().reply(Reply::ok(data(5)).after(Duration::from_millis(200)));
+        let client = Arc::new(app(repo.clone()));
+
+        let original = {
+            let client = client.clone();
+            tokio::spawn(async move {
+                client.post("/data").header(IDEMPOTENCY_KEY, "abc").json(&data_body(5)).send().await.status()
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let res = client.post("/data").header(IDEMPOTENCY_KEY, "abc").json(&data_body(5)).send().await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        assert_eq!(original.await.unwrap(), StatusCode::CREATED);
+        repo.assert_call_count(1);
+    }
+
+    #[tokio::test]
+    async fn test_only_final_responses_are_stored() {
+        let repo = MockDataRepo::new()
+            .reply(Reply::err(DataRepoError::Unavailable))
+            .reply(Reply::err(DataRepoError::PreconditionFailed))
+            .reply(Reply::err(DataRepoError::Conflict))
+            .reply(Reply::ok(data(5)));
+        let client = app(repo.clone());
+
+        let post = || client.post("/data").header(IDEMPOTENCY_KEY, "abc").json(&data_body(5));
+
+        for status in [
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::PRECONDITION_FAILED,
+            StatusCode::CONFLICT,
+            StatusCode::CREATED,
+        ] {
+            assert_eq!(post().send().await.status(), status);
+        }
+        repo.assert_call_count(4);
+
+        let res = post().send().await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res.headers()[IDEMPOTENT_REPLAYED], "true");
+        repo.assert_call_count(4);
+
+        // Rejections of the request itself are final
+        let invalid = serde_json::json!({"id": 6});
+        let res = client.post("/data").header(IDEMPOTENCY_KEY, "def").json(&invalid).send().await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let res = client.post("/data").header(IDEMPOTENCY_KEY, "def").json(&invalid).send().await;
+        assert_eq!(res.headers()[IDEMPOTENT_REPLAYED], "true");
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_scoped_to_the_caller() {
+        let repo = MockDataRepo::new().reply(Reply::ok(data(5)));
+        let client = TestApp::builder()
+            .with_data_repo(repo.clone())
+            .with_user(User::new("alice"))
+            .with_user(User::new("bob"))
+            .build();
+
+        let post = |token: &str| {
+            client
+                .post("/data")
+                .bearer(token)
+                .header(IDEMPOTENCY_KEY, "abc")
+                .json(&data_body(5))
+        };
+
+        assert_eq!(post("alice").send().await.status(), StatusCode::CREATED);
+        assert_eq!(post("alice").send().await.headers()[IDEMPOTENT_REPLAYED], "true");
+        repo.assert_call_count(1);
+
+        // The same key and body from someone else is a request of its own
+        let res = post("bob").send().await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert!(res.headers().get(IDEMPOTENT_REPLAYED).is_none());
+        repo.assert_call_count(2);
+    }
+
+    #[tokio::test]
+    async fn test_reads_ignore_the_key() {
+        let repo = MockDataRepo::new().reply(Reply::ok(data(1)));
+        let client = app(repo.clone());
+
+        for _ in 0..2 {
+            let res = client
+                .post("/data/batch")
+                .header(IDEMPOTENCY_KEY, "abc")
+                .json(&serde_json::json!({"ids": [1]}))
+                .send()
+                .await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(res.headers().get(IDEMPOTENT_REPLAYED).is_none());
+        }
+
+        repo.assert_call_count(2);
+    }
+
+    #[derive(Default)]
+    struct CountingStore {
+        inner: InMemoryIdempotencyStore,
+        reserved: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl IdempotencyStore for CountingStore {
+        async fn reserve(&self, key: &str, fingerprint: &str) -> Option<IdempotencyEntry> {
+            self.reserved.fetch_add(1, Ordering::SeqCst);
+            self.inner.reserve(key, fingerprint).await
+        }
+
+        async fn complete(&self, key: &str, response: StoredResponse) {
+            self.inner.complete(key, response).await
+        }
+
+        async fn release(&self, key: &str) {
+            self.inner.release(key).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_callers_dont_reserve_keys() {
+        let store = Arc::new(CountingStore::default());
+        let client = TestApp::builder()
+            .with_policy(ScopePolicy)
+            .with_user(User::new("reader").with_scopes(["data:read"]))
+            .with_service::<dyn IdempotencyStore + Send + Sync>(store.clone())
+            .build();
+
+        let res = client.post("/data").header(IDEMPOTENCY_KEY, "abc").json(&data_body(1)).send().await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = client
+            .post("/data")
+            .bearer("reader")
+            .header(IDEMPOTENCY_KEY, "abc")
+            .json(&data_body(1))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = client
+            .patch("/data/1")
+            .bearer("reader")
+            .header(IDEMPOTENCY_KEY, "abc")
+            .json(&serde_json::json!({"name": "one"}))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        assert_eq!(store.reserved.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store_forgets_expired_keys() {
+        let store = InMemoryIdempotencyStore::new(Duration::from_millis(20));
+
+        assert!(store.reserve("abc", "one").await.is_none());
+        let entry = store.reserve("abc", "two").await.unwrap();
+        assert_eq!(entry.fingerprint, "one");
+        assert!(entry.response.is_none());
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(store.reserve("abc", "two").await.is_none());
+
+        store.release("abc").await;
+        assert!(store.reserve("abc", "three").await.is_none());
+    }
+}
@@ -0,0 +1,139 @@
+use std::convert::Infallible;
+use std::ops::Deref;
+
+use axum::async_trait;
+use axum::extract::{FromRef, FromRequestParts};
+use http::request::Parts;
+
+/// Extracts any dependency that can be produced from the router state through [`FromRef`]. Adding
+/// a new service to the state only requires a `FromRef` impl for it, after which handlers can
+/// take `Inject<DynMyService>` without a dedicated extractor type.
+pub struct Inject<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for Inject<T>
+where
+    T: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Inject(T::from_ref(state)))
+    }
+}
+
+impl<T> Deref for Inject<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authz::AllowAll;
+    use crate::data_repos::InMemoryDataRepo;
+    use crate::test_helpers::*;
+    use crate::container::Container;
+    use crate::{AppState, DataRepo, DataRepoError, DynDataRepo};
+
+    use std::sync::Arc;
+
+    use axum::extract::Path;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use http::StatusCode;
+
+    trait Clock {
+        fn now(&self) -> u64;
+    }
+
+    type DynClock = Arc<dyn Clock + Send + Sync>;
+
+    struct FixedClock(u64);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> u64 {
+            self.0
+        }
+    }
+
+    #[derive(Clone)]
+    struct MockState {
+        clock: DynClock,
+        data_repo: DynDataRepo,
+    }
+
+    impl FromRef<MockState> for DynClock {
+        fn from_ref(state: &MockState) -> Self {
+            state.clock.clone()
+        }
+    }
+
+    impl FromRef<MockState> for DynDataRepo {
+        fn from_ref(state: &MockState) -> Self {
+            state.data_repo.clone()
+        }
+    }
+
+    async fn stamped_handler(
+        Path(id): Path<usize>,
+        Inject(clock): Inject<DynClock>,
+        Inject(data_repo): Inject<DynDataRepo>,
+    ) -> Result<Json<serde_json::Value>, DataRepoError> {
+        let data = data_repo.retrieve(id).await?;
+        Ok(Json(serde_json::json!({"id": data.id, "at": clock.now()})))
+    }
+
+    #[tokio::test]
+    async fn test_injects_multiple_services() {
+        let state = MockState {
+            clock: Arc::new(FixedClock(1_234)),
+            data_repo: Arc::new([data(50)].into_iter().collect::<InMemoryDataRepo>()),
+        };
+
+        let app = Router::new().route("/:id", get(stamped_handler)).with_state(state);
+
+        let client = TestClient::new(app);
+
+        let res = client.get("/50").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: serde_json::Value = res.json().await;
+        assert_eq!(body["id"], 50);
+        assert_eq!(body["at"], 1_234);
+
+        let res = client.get("/51").send().await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    async fn container_handler(
+        Path(id): Path<usize>,
+        Inject(container): Inject<Arc<Container>>,
+    ) -> Result<Json<serde_json::Value>, DataRepoError> {
+        let data_repo = container
+            .resolve::<dyn DataRepo + Send + Sync>()
+            .expect("AppState to register a data repo");
+        let data = data_repo.retrieve(id).await?;
+        Ok(Json(serde_json::json!({"id": data.id})))
+    }
+
+    #[tokio::test]
+    async fn test_injects_from_app_state() {
+        let data_repo: InMemoryDataRepo = [data(7)].into_iter().collect();
+        let app_state = AppState::new(Arc::new(data_repo)).with_policy(Arc::new(AllowAll));
+
+        let app = Router::new().route("/:id", get(container_handler)).with_state(app_state);
+
+        let client = TestClient::new(app);
+
+        let res = client.get("/7").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: serde_json::Value = res.json().await;
+        assert_eq!(body["id"], 7);
+    }
+}
@@ -0,0 +1,233 @@
+use std::any::Any;
+
+use axum::body::Body;
+use axum::extract::DefaultBodyLimit;
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use http::{Request, StatusCode};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower_http::catch_panic::CatchPanicLayer;
+use tower_http::compression::CompressionLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::timeout::TimeoutLayer;
+use tower_http::trace::TraceLayer;
+use tracing::Span;
+
+use crate::config::Config;
+use crate::problem::Problem;
+
+/// Wraps every route in `router` with the production middleware stack. Layers are added from the
+/// innermost outwards, so a request passes through them in the reverse of the order below:
+/// request id, tracing, panic catching, compression, timeout, concurrency limit and finally the
+/// body size limit enforced by the extractors.
+pub(crate) fn apply_middleware(router: Router, config: &Config) -> Router {
+    let http = &config.http;
+
+    let mut router = router
+        .layer(DefaultBodyLimit::max(http.body_limit_bytes))
+        // A single semaphore shared by every route, rather than one per route
+        .layer(GlobalConcurrencyLimitLayer::new(http.concurrency_limit))
+        .layer(TimeoutLayer::new(config.timeouts.request()));
+
+    if http.compression {
+        router = router.layer(CompressionLayer::new());
+    }
+
+    if http.catch_panics {
+        router = router.layer(CatchPanicLayer::custom(panic_response));
+    }
+
+    if http.trace {
+        router = router.layer(TraceLayer::new_for_http().make_span_with(request_span));
+    }
+
+    if http.request_id {
+        router = router
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
+    }
+
+    router
+}
+
+fn request_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("-");
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id
+    )
+}
+
+fn panic_response(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown panic payload");
+
+    tracing::error!(panic = message, "request handler panicked");
+
+    Problem::new(StatusCode::INTERNAL_SERVER_ERROR).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::routing::{get, post};
+    use bytes::Bytes;
+
+    async fn slow_handler() -> &'static str {
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        "done"
+    }
+
+    async fn panicking_handler() -> &'static str {
+        panic!("handler exploded");
+    }
+
+    async fn echo_handler(body: Bytes) -> Bytes {
+        body
+    }
+
+    async fn large_handler() -> String {
+        "compress me please ".repeat(512)
+    }
+
+    fn test_app(config: &Config) -> Router {
+        let router = Router::new()
+            .route("/slow", get(slow_handler))
+            .route("/panic", get(panicking_handler))
+            .route("/echo", post(echo_handler))
+            .route("/large", get(large_handler));
+
+        apply_middleware(router, config)
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_generated_and_propagated() {
+        let client = TestClient::new(test_app(&Config::default()));
+
+        let res = client.get("/large").send().await;
+        let generated = res.headers()["x-request-id"].to_str().unwrap();
+        assert_eq!(generated.len(), 36);
+
+        let res = client.get("/large").header("x-request-id", "abc-123").send().await;
+        assert_eq!(res.headers()["x-request-id"], "abc-123");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_can_be_disabled() {
+        let mut config = Config::default();
+        config.http.request_id = false;
+        let client = TestClient::new(test_app(&config));
+
+        let res = client.get("/large").send().await;
+        assert!(res.headers().get("x-request-id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_slow_requests_time_out() {
+        let mut config = Config::default();
+        config.timeouts.request_secs = 1;
+        let client = TestClient::new(test_app(&config));
+
+        let res = client.get("/slow").send().await;
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_bodies_are_rejected() {
+        let mut config = Config::default();
+        config.http.body_limit_bytes = 16;
+        let client = TestClient::new(test_app(&config));
+
+        let res = client.post("/echo").body("small").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.text().await, "small");
+
+        let res = client.post("/echo").body("x".repeat(32)).send().await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_panics_become_problem_responses() {
+        let client = TestClient::new(test_app(&Config::default()));
+
+        let res = client.get("/panic").send().await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(res.headers()["content-type"], crate::problem::PROBLEM_JSON);
+
+        let body = res.text().await;
+        assert!(!body.contains("exploded"));
+
+        // The server keeps serving after a handler panics
+        let res = client.get("/large").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_compression_follows_config() {
+        let client = TestClient::new(test_app(&Config::default()));
+
+        let res = client.get("/large").header("accept-encoding", "gzip").send().await;
+        assert_eq!(res.headers()["content-encoding"], "gzip");
+
+        let mut config = Config::default();
+        config.http.compression = false;
+        let client = TestClient::new(test_app(&config));
+
+        let res = client.get("/large").header("accept-encoding", "gzip").send().await;
+        assert!(res.headers().get("content-encoding").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_is_limited_across_routes() {
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let handler = {
+            let active = active.clone();
+            let peak = peak.clone();
+
+            move || async move {
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                active.fetch_sub(1, Ordering::SeqCst);
+                "ok"
+            }
+        };
+
+        let mut config = Config::default();
+        config.http.concurrency_limit = 1;
+
+        let router = Router::new()
+            .route("/a", get(handler.clone()))
+            .route("/b", get(handler));
+        let client = TestClient::new(apply_middleware(router, &config));
+
+        let (a, b, c) = tokio::join!(
+            client.get("/a").send(),
+            client.get("/b").send(),
+            client.get("/a").send(),
+        );
+        assert_eq!(a.status(), StatusCode::OK);
+        assert_eq!(b.status(), StatusCode::OK);
+        assert_eq!(c.status(), StatusCode::OK);
+
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,1180 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{async_trait, BoxError, Json, Router, Server};
+use axum::extract::{OriginalUri, Path};
+use axum::handler::Handler;
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use http::StatusCode;
+
+use crate::audit::AuditTrailFactory;
+use crate::auth::{Authenticator, ChainAuthenticator, CurrentUser, DynAuthenticator, User};
+use crate::authz::{Admin, AuthorizationPolicy, Authorized, DynAuthorizationPolicy, Read, ScopePolicy, Write};
+use crate::conditional::{not_modified, IfMatch, IfNoneMatch, Tagged};
+use crate::config::{Config, RepoBackend, RepoConfig};
+use crate::data_repos::{InMemoryDataRepo, InstrumentedDataRepo, SqliteDataRepo};
+use crate::health::{healthz_handler, readyz_handler};
+use crate::idempotency::{idempotency, IdempotencyState};
+use crate::layers::apply_middleware;
+use crate::listing::{ListQuery, Page, PageResponse};
+use crate::metrics::{metrics_handler, track_http_metrics};
+use crate::problem::Problem;
+use crate::scope::request_scope;
+use crate::validation::Valid;
+
+mod audit;
+pub mod auth;
+pub mod authz;
+mod batch;
+pub mod conditional;
+pub mod config;
+mod container;
+mod data;
+mod data_repos;
+mod health;
+mod idempotency;
+mod inject;
+mod layers;
+pub mod listing;
+mod metrics;
+mod problem;
+mod scope;
+pub mod validation;
+
+pub use crate::audit::AuditTrail;
+pub use crate::batch::{BatchRequest, BatchResponse, BatchResult};
+pub use crate::container::{Container, Dependency, MissingDependency, Resolved};
+pub use crate::data_repos::{CachedDataRepo, CoalescingDataRepo, ResilientDataRepo};
+pub use crate::data::{CreateData, Data, DataInput, DataPatch, Revision};
+pub use crate::health::HealthStatus;
+pub use crate::idempotency::{
+    DynIdempotencyStore, IdempotencyEntry, IdempotencyStore, InMemoryIdempotencyStore, StoredResponse,
+};
+pub use crate::inject::Inject;
+pub use crate::metrics::Metrics;
+pub use crate::scope::{ScopeOutcome, Scoped, ScopedFactory};
+
+#[cfg(test)]
+mod test_helpers;
+
+#[derive(Clone)]
+pub struct AppState {
+    container: Arc<Container>,
+}
+
+impl AppState {
+    pub fn new(data_repo: DynDataRepo) -> Self {
+        let metrics = Arc::new(Metrics::default());
+        let data_repo: DynDataRepo = Arc::new(InstrumentedDataRepo::new(data_repo, metrics.clone()));
+
+        let mut container = Container::default();
+        container
+            .register::<dyn DataRepo + Send + Sync>(data_repo)
+            .register_health_check("data_repo", |repo: DynDataRepo| async move { repo.health_check().await })
+            .register(metrics)
+            .register::<dyn IdempotencyStore + Send + Sync>(Arc::new(InMemoryIdempotencyStore::default()))
+            .register_health_check("idempotency_store", |store: DynIdempotencyStore| async move {
+                store.health_check().await
+            })
+            // Secure by default: until an authenticator is configured no token is accepted
+            .register::<dyn Authenticator + Send + Sync>(Arc::new(ChainAuthenticator::default()))
+            .register::<dyn AuthorizationPolicy + Send + Sync>(Arc::new(ScopePolicy))
+            .register_scoped::<AuditTrail, _>(AuditTrailFactory);
+
+        Self {
+            container: Arc::new(container),
+        }
+    }
+
+    /// The services the routes in [`serve`] resolve while handling requests.
+    fn required_services() -> [Dependency; 6] {
+        [
+            Dependency::of::<dyn Authenticator + Send + Sync>(),
+            Dependency::of::<dyn AuthorizationPolicy + Send + Sync>(),
+            Dependency::of::<dyn DataRepo + Send + Sync>(),
+            Dependency::of::<Metrics>(),
+            Dependency::of::<dyn IdempotencyStore + Send + Sync>(),
+            Dependency::scoped::<AuditTrail>(),
+        ]
+    }
+
+    /// Replaces the authenticator used to identify the [`CurrentUser`] of a request.
+    pub fn with_authenticator(mut self, authenticator: DynAuthenticator) -> Self {
+        self.container_mut().register(authenticator);
+        self
+    }
+
+    /// Replaces the policy deciding which callers the data routes let through.
+    pub fn with_policy(mut self, policy: DynAuthorizationPolicy) -> Self {
+        self.container_mut().register(policy);
+        self
+    }
+
+    /// The container holding the services, for registering or decorating them before the app is
+    /// built. Changes only affect this state and the clones taken from it afterwards.
+    pub fn container_mut(&mut self) -> &mut Container {
+        Arc::make_mut(&mut self.container)
+    }
+
+    /// Checks that every service a route depends on has been registered, so a wiring mistake is
+    /// reported at startup instead of on the first request that needs it.
+    pub fn verify(&self) -> Result<(), MissingDependency> {
+        self.container.verify(&Self::required_services())
+    }
+
+    /// Gives every injected dependency a chance to flush and release its resources once the
+    /// server has stopped accepting requests.
+    pub async fn shutdown(&self) {
+        if let Ok(data_repo) = self.container.resolve::<dyn DataRepo + Send + Sync>() {
+            data_repo.shutdown().await;
+        }
+    }
+
+    /// Reports the health of every injected dependency registered with a health check, keyed by
+    /// its name.
+    async fn health_checks(&self) -> BTreeMap<&'static str, HealthStatus> {
+        self.container.health_checks().await
+    }
+}
+
+#[async_trait]
+pub trait DataRepo {
+    async fn create(&self, data: Data) -> Result<Data, DataRepoError>;
+
+    async fn retrieve(&self, id: usize) -> Result<Data, DataRepoError>;
+
+    /// Retrieves each of `ids`, answering in the same order. The default makes every `retrieve`
+    /// call concurrently; backends that can fetch several records in one round trip should
+    /// override it.
+    async fn retrieve_many(&self, ids: &[usize]) -> Vec<Result<Data, DataRepoError>> {
+        futures::future::join_all(ids.iter().map(|&id| self.retrieve(id))).await
+    }
+
+    /// Replaces the fields of record `id` with those of `data`. Implementations keep the stored
+    /// creation time and move the record to its next version, see [`Data::revise`].
+    ///
+    /// When `expected` is given the replacement only happens if the stored record is still at that
+    /// revision, checked atomically with the write, and fails with
+    /// [`DataRepoError::PreconditionFailed`] otherwise.
+    async fn update(&self, id: usize, data: Data, expected: Option<Revision>) -> Result<Data, DataRepoError>;
+
+    async fn delete(&self, id: usize) -> Result<(), DataRepoError>;
+
+    /// Fetches one page of the records matching `query`, in the order it asks for.
+    async fn list(&self, query: &ListQuery) -> Result<Page<Data>, DataRepoError>;
+
+    async fn health_check(&self) -> HealthStatus {
+        HealthStatus::Healthy
+    }
+
+    async fn shutdown(&self) {}
+}
+
+#[derive(Clone, Debug)]
+pub enum DataRepoError {
+    NotFound,
+    InvalidRequest,
+    Conflict,
+    PreconditionFailed,
+    Unavailable,
+    Timeout,
+    Internal(Arc<dyn std::error::Error + Send + Sync>),
+}
+
+impl DataRepoError {
+    fn internal(err: impl std::error::Error + Send + Sync + 'static) -> Self {
+        DataRepoError::Internal(Arc::new(err))
+    }
+
+    /// A short, stable identifier for the variant, suitable for metric labels.
+    fn kind(&self) -> &'static str {
+        match self {
+            DataRepoError::NotFound => "not_found",
+            DataRepoError::InvalidRequest => "invalid_request",
+            DataRepoError::Conflict => "conflict",
+            DataRepoError::PreconditionFailed => "precondition_failed",
+            DataRepoError::Unavailable => "unavailable",
+            DataRepoError::Timeout => "timeout",
+            DataRepoError::Internal(_) => "internal",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            DataRepoError::NotFound => StatusCode::NOT_FOUND,
+            DataRepoError::InvalidRequest => StatusCode::BAD_REQUEST,
+            DataRepoError::Conflict => StatusCode::CONFLICT,
+            DataRepoError::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            DataRepoError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            DataRepoError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            DataRepoError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Display for DataRepoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataRepoError::NotFound => f.write_str("the requested record does not exist"),
+            DataRepoError::InvalidRequest => f.write_str("the request was not valid for this repository"),
+            DataRepoError::Conflict => f.write_str("the request conflicts with an existing record"),
+            DataRepoError::PreconditionFailed => f.write_str("the record has changed since the version the request expected"),
+            DataRepoError::Unavailable => f.write_str("the data backend is unavailable"),
+            DataRepoError::Timeout => f.write_str("the data backend did not respond in time"),
+            DataRepoError::Internal(err) => write!(f, "internal data backend error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for DataRepoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DataRepoError::Internal(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl IntoResponse for DataRepoError {
+    fn into_response(self) -> Response {
+        let problem = Problem::new(self.status());
+
+        match &self {
+            // Backend details stay in the logs rather than leaking to the client
+            DataRepoError::Internal(_) => {
+                tracing::error!(error = %self, "data repo request failed");
+                problem.into_response()
+            }
+            _ => problem.with_detail(self.to_string()).into_response(),
+        }
+    }
+}
+
+pub type DynDataRepo = Arc<dyn DataRepo + Send + Sync>;
+
+/// Lets a shared repo, such as a [`DynDataRepo`], be used wherever a `DataRepo` is expected, for
+/// instance as the inner repo of a decorator.
+#[async_trait]
+impl<T: DataRepo + Send + Sync + ?Sized> DataRepo for Arc<T> {
+    async fn create(&self, data: Data) -> Result<Data, DataRepoError> {
+        (**self).create(data).await
+    }
+
+    async fn retrieve(&self, id: usize) -> Result<Data, DataRepoError> {
+        (**self).retrieve(id).await
+    }
+
+    async fn retrieve_many(&self, ids: &[usize]) -> Vec<Result<Data, DataRepoError>> {
+        (**self).retrieve_many(ids).await
+    }
+
+    async fn update(&self, id: usize, data: Data, expected: Option<Revision>) -> Result<Data, DataRepoError> {
+        (**self).update(id, data, expected).await
+    }
+
+    async fn delete(&self, id: usize) -> Result<(), DataRepoError> {
+        (**self).delete(id).await
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<Page<Data>, DataRepoError> {
+        (**self).list(query).await
+    }
+
+    async fn health_check(&self) -> HealthStatus {
+        (**self).health_check().await
+    }
+
+    async fn shutdown(&self) {
+        (**self).shutdown().await
+    }
+}
+
+impl axum::extract::FromRef<AppState> for Arc<Container> {
+    fn from_ref(state: &AppState) -> Self {
+        state.container.clone()
+    }
+}
+
+struct ProdDataRepo;
+
+impl ProdDataRepo {
+    fn record(id: usize) -> Data {
+        // Records here never change, so they keep one creation time and with it stable ETags
+        Data {
+            created_at: time::OffsetDateTime::UNIX_EPOCH,
+            updated_at: time::OffsetDateTime::UNIX_EPOCH,
+            ..Data::new(id, DataInput::new(format!("record {id}")))
+        }
+    }
+
+    fn check_id(id: usize) -> Result<(), DataRepoError> {
+        if id >= 1_024 {
+            Err(DataRepoError::InvalidRequest)
+        } else if id > 10 {
+            Err(DataRepoError::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl DataRepo for ProdDataRepo {
+    async fn create(&self, data: Data) -> Result<Data, DataRepoError> {
+        if data.id >= 1_024 {
+            return Err(DataRepoError::InvalidRequest);
+        }
+
+        Ok(data)
+    }
+
+    async fn retrieve(&self, id: usize) -> Result<Data, DataRepoError> {
+        Self::check_id(id)?;
+        Ok(Self::record(id))
+    }
+
+    async fn update(&self, id: usize, data: Data, expected: Option<Revision>) -> Result<Data, DataRepoError> {
+        Self::check_id(id)?;
+        if data.id != id {
+            return Err(DataRepoError::InvalidRequest);
+        }
+
+        let record = Self::record(id);
+        match expected {
+            Some(expected) if expected != record.revision() => Err(DataRepoError::PreconditionFailed),
+            _ => Ok(record.revise(data)),
+        }
+    }
+
+    async fn delete(&self, id: usize) -> Result<(), DataRepoError> {
+        Self::check_id(id)
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<Page<Data>, DataRepoError> {
+        Ok(query.paginate((0..=10).map(Self::record)))
+    }
+}
+
+pub async fn basic_handler() -> Response {
+    (StatusCode::OK, Json(serde_json::json!({"id": 100}))).into_response()
+}
+
+pub async fn me_handler(CurrentUser(user): CurrentUser) -> Json<User> {
+    Json(user)
+}
+
+pub async fn data_state_handler(
+    _: Authorized<Read>,
+    Path(id): Path<usize>,
+    Resolved(data_repo): Resolved<dyn DataRepo + Send + Sync>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response, DataRepoError> {
+    let data = data_repo.retrieve(id).await?;
+
+    if if_none_match.matches(&data) {
+        return Ok(not_modified(&data));
+    }
+
+    Ok(Tagged(data).into_response())
+}
+
+pub async fn data_batch_handler(
+    _: Authorized<Read>,
+    Resolved(data_repo): Resolved<dyn DataRepo + Send + Sync>,
+    Valid(request): Valid<BatchRequest>,
+) -> Result<Json<BatchResponse>, DataRepoError> {
+    let retrieved = data_repo.retrieve_many(&request.parsed_ids()).await;
+    Ok(Json(BatchResponse::new(request, retrieved)?))
+}
+
+pub async fn data_list_handler(
+    _: Authorized<Read>,
+    OriginalUri(uri): OriginalUri,
+    Resolved(data_repo): Resolved<dyn DataRepo + Send + Sync>,
+    query: ListQuery,
+) -> Result<Json<PageResponse<Data>>, DataRepoError> {
+    let page = data_repo.list(&query).await?;
+    Ok(Json(PageResponse::new(uri.path(), &query, page)))
+}
+
+pub async fn data_create_handler(
+    _: Authorized<Write>,
+    Resolved(data_repo): Resolved<dyn DataRepo + Send + Sync>,
+    Scoped(audit): Scoped<AuditTrail>,
+    Valid(create): Valid<CreateData>,
+) -> Result<(StatusCode, Tagged), DataRepoError> {
+    let data = data_repo.create(Data::new(create.id, create.input)).await?;
+    audit.record(format!("created data {}", data.id));
+    Ok((StatusCode::CREATED, Tagged(data)))
+}
+
+pub async fn data_update_handler(
+    _: Authorized<Write>,
+    Path(id): Path<usize>,
+    Resolved(data_repo): Resolved<dyn DataRepo + Send + Sync>,
+    Scoped(audit): Scoped<AuditTrail>,
+    IfMatch(expected): IfMatch,
+    Valid(input): Valid<DataInput>,
+) -> Result<Tagged, DataRepoError> {
+    let data = data_repo.update(id, Data::new(id, input), expected).await?;
+    audit.record(format!("updated data {id}"));
+    Ok(Tagged(data))
+}
+
+/// How many times a `PATCH` without `If-Match` is merged again onto a record that changed while it
+/// was being applied.
+const PATCH_ATTEMPTS: usize = 3;
+
+/// Applies the fields sent onto the stored record. The write only goes through if the record is
+/// still at the revision the patch was merged onto, which is the one named by `If-Match` if the
+/// client sent it.
+pub async fn data_patch_handler(
+    _: Authorized<Write>,
+    Path(id): Path<usize>,
+    Resolved(data_repo): Resolved<dyn DataRepo + Send + Sync>,
+    Scoped(audit): Scoped<AuditTrail>,
+    IfMatch(expected): IfMatch,
+    Valid(patch): Valid<DataPatch>,
+) -> Result<Tagged, DataRepoError> {
+    let mut attempts = 0;
+    let data = loop {
+        attempts += 1;
+
+        let current = data_repo.retrieve(id).await?;
+        let revision = expected.unwrap_or_else(|| current.revision());
+
+        match data_repo
+            .update(id, Data::new(id, patch.apply_to(&current)), Some(revision))
+            .await
+        {
+            Err(DataRepoError::PreconditionFailed) if expected.is_none() && attempts < PATCH_ATTEMPTS => continue,
+            result => break result?,
+        }
+    };
+
+    audit.record(format!("patched data {id}"));
+    Ok(Tagged(data))
+}
+
+pub async fn data_delete_handler(
+    _: Authorized<Admin>,
+    Path(id): Path<usize>,
+    Resolved(data_repo): Resolved<dyn DataRepo + Send + Sync>,
+    Scoped(audit): Scoped<AuditTrail>,
+) -> Result<StatusCode, DataRepoError> {
+    data_repo.delete(id).await?;
+    audit.record(format!("deleted data {id}"));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn data_extract_handler(
+    _: Authorized<Read>,
+    Path(id): Path<usize>,
+    Resolved(data_repo): Resolved<dyn DataRepo + Send + Sync>,
+) -> Result<Json<Data>, DataRepoError> {
+    Ok(Json(data_repo.retrieve(id).await?))
+}
+
+/// Opens the data repo selected by `config`, running any pending migrations.
+pub async fn build_data_repo(config: &RepoConfig) -> Result<DynDataRepo, Box<dyn std::error::Error>> {
+    let data_repo: DynDataRepo = match config.backend {
+        RepoBackend::Memory => Arc::new(InMemoryDataRepo::default()),
+        RepoBackend::Prod => Arc::new(ProdDataRepo),
+        RepoBackend::Sqlite => {
+            let repo = SqliteDataRepo::connect(&config.sqlite_url).await?;
+            repo.migrate().await?;
+            Arc::new(repo)
+        }
+    };
+
+    Ok(data_repo)
+}
+
+/// Binds the configured address and serves the app until the process is asked to stop.
+pub async fn run_server(config: &Config, app_state: AppState) -> Result<(), BoxError> {
+    let listener = std::net::TcpListener::bind(config.bind_addr)?;
+
+    serve(listener, config, app_state, shutdown_signal()).await
+}
+
+/// Serves requests on `listener` until `shutdown` resolves, then stops accepting connections and
+/// gives in-flight requests up to the configured drain timeout to finish before the dependencies
+/// in `app_state` are shut down.
+async fn serve(
+    listener: std::net::TcpListener,
+    config: &Config,
+    app_state: AppState,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), BoxError> {
+    let app = build_app(app_state.clone(), config)?;
+
+    tracing::info!(addr = ?listener.local_addr()?, "server listening");
+
+    let result = drain_on_shutdown(listener, app, shutdown, config.timeouts.shutdown()).await;
+    app_state.shutdown().await;
+
+    result
+}
+
+/// The application's route table wrapped in the configured middleware stack. This is everything
+/// [`run_server`] serves, so embedders and tests can mount the same app on their own listener.
+/// Fails if a service the routes depend on was never registered, see [`AppState::verify`].
+pub fn build_app(app_state: AppState, config: &Config) -> Result<Router, MissingDependency> {
+    app_state.verify()?;
+
+    let idempotency_store = app_state.container.resolve::<dyn IdempotencyStore + Send + Sync>()?;
+    let metrics = app_state.container.resolve::<Metrics>()?;
+
+    // Only the routes that create or change records take an Idempotency-Key, reads like the batch
+    // retrieval are safe to retry as they are
+    let idempotent = middleware::from_fn_with_state(
+        IdempotencyState::new(idempotency_store, app_state.container.clone(), config.http.body_limit_bytes),
+        idempotency,
+    );
+
+    let router = Router::new()
+        .route("/", get(basic_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/me", get(me_handler))
+        .route(
+            "/data",
+            get(data_list_handler).post(data_create_handler.layer(idempotent.clone())),
+        )
+        .route("/data/batch", post(data_batch_handler))
+        .route(
+            "/data/:id",
+            get(data_state_handler)
+                .put(data_update_handler)
+                .patch(data_patch_handler.layer(idempotent))
+                .delete(data_delete_handler),
+        )
+        .route("/pot/:id", get(data_extract_handler))
+        .route("/metrics", get(metrics_handler))
+        .route_layer(middleware::from_fn_with_state(metrics, track_http_metrics))
+        .layer(middleware::from_fn(request_scope))
+        .with_state(app_state);
+
+    Ok(apply_middleware(router, config))
+}
+
+async fn drain_on_shutdown(
+    listener: std::net::TcpListener,
+    app: Router,
+    shutdown: impl Future<Output = ()>,
+    drain_timeout: Duration,
+) -> Result<(), BoxError> {
+    let (drain_tx, drain_rx) = tokio::sync::oneshot::channel::<()>();
+
+    let server = Server::from_tcp(listener)?
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(async {
+            let _ = drain_rx.await;
+        });
+
+    tokio::pin!(server);
+    tokio::pin!(shutdown);
+
+    tokio::select! {
+        result = &mut server => return result.map_err(Into::into),
+        _ = &mut shutdown => {}
+    }
+
+    tracing::info!(timeout = ?drain_timeout, "shutdown requested, draining in-flight requests");
+    let _ = drain_tx.send(());
+
+    match tokio::time::timeout(drain_timeout, server).await {
+        Ok(result) => result.map_err(Into::into),
+        Err(_) => {
+            tracing::warn!("drain timeout elapsed, abandoning remaining connections");
+            Ok(())
+        }
+    }
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %err, "unable to listen for SIGINT");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                tracing::error!(error = %err, "unable to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authz::AllowAll;
+    use crate::conditional::entity_tag;
+    use crate::test_helpers::*;
+
+    use axum::Router;
+    use axum::routing::get;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Response {
+        id: usize,
+    }
+
+    #[tokio::test]
+    async fn test_basic_handler() {
+        let app = Router::new().route("/", get(basic_handler));
+
+        let client = TestClient::new(app);
+
+        let res = client.get("/").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: Response = res.json().await;
+        assert_eq!(body.id, 100);
+    }
+
+    #[tokio::test]
+    async fn test_mocked_data_state_handler() {
+        let repo = MockDataRepo::new().reply_for(50, Reply::ok(data(50)));
+        let client = TestApp::builder().with_data_repo(repo.clone()).build();
+
+        let res = client.get("/data/50").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: Response = res.json().await;
+        assert_eq!(body.id, 50);
+
+        repo.assert_call_count(1);
+        repo.assert_called_with(50);
+    }
+
+    fn mocked_crud_app(result: Result<Data, DataRepoError>) -> TestClient {
+        TestApp::builder().with_data_repo(MockDataRepo::new().reply(result)).build()
+    }
+
+    #[tokio::test]
+    async fn test_mocked_create_handler() {
+        let client = mocked_crud_app(Ok(data(50)));
+
+        let res = client.post("/data").json(&data_body(50)).send().await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let body: Response = res.json().await;
+        assert_eq!(body.id, 50);
+    }
+
+    #[tokio::test]
+    async fn test_create_handler_reports_every_invalid_field() {
+        let repo = MockDataRepo::new();
+        let client = TestApp::builder().with_data_repo(repo.clone()).build();
+
+        let body = serde_json::json!({"id": "x", "name": " ", "tags": ["ok", "Bad", "ok"], "extra": 1});
+        let res = client.post("/data").json(&body).send().await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(res.headers()["content-type"], crate::problem::PROBLEM_JSON);
+
+        let body: serde_json::Value = res.json().await;
+        let fields: Vec<&str> = body["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, ["id", "name", "tags[1]", "tags[2]", "extra"]);
+
+        repo.assert_call_count(0);
+    }
+
+    #[tokio::test]
+    async fn test_mocked_create_handler_invalid() {
+        let client = mocked_crud_app(Err(DataRepoError::InvalidRequest));
+
+        let res = client.post("/data").json(&data_body(5_000)).send().await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_batch_handler_reports_each_id() {
+        let repo: InMemoryDataRepo = [data(1), data(2)].into_iter().collect();
+        let client = TestApp::builder().with_data_repo(repo).build();
+
+        let body = serde_json::json!({"ids": [2, 9, "one", 1]});
+        let res = client.post("/data/batch").json(&body).send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: serde_json::Value = res.json().await;
+        let results = body["results"].as_array().unwrap();
+        let statuses: Vec<&str> = results.iter().map(|result| result["status"].as_str().unwrap()).collect();
+        assert_eq!(statuses, ["found", "not_found", "invalid", "found"]);
+        assert_eq!(results[0]["data"]["id"], 2);
+        assert_eq!(results[2]["id"], "one");
+
+        for ids in [serde_json::json!([]), serde_json::json!((0..=100).collect::<Vec<_>>())] {
+            let res = client.post("/data/batch").json(&serde_json::json!({"ids": ids})).send().await;
+            assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_handler_fails_on_backend_errors() {
+        let client = mocked_crud_app(Err(DataRepoError::Unavailable));
+
+        let res = client.post("/data/batch").json(&serde_json::json!({"ids": [1, 2]})).send().await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_mocked_list_handler() {
+        let client = mocked_crud_app(Ok(data(50)));
+
+        let res = client.get("/data").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: serde_json::Value = res.json().await;
+        assert_eq!(body["items"][0]["id"], 50);
+        assert!(body.get("next_cursor").is_none());
+    }
+
+    fn item_ids(body: &serde_json::Value) -> Vec<u64> {
+        body["items"].as_array().unwrap().iter().map(|item| item["id"].as_u64().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_handler_pages_through_records() {
+        let repo: InMemoryDataRepo = (1..=5).map(data).collect();
+        let client = TestApp::builder().with_data_repo(repo).build();
+
+        let res = client.get("/data?limit=2&sort=-id").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: serde_json::Value = res.json().await;
+        assert_eq!(item_ids(&body), [5, 4]);
+
+        let next = body["links"]["next"].as_str().unwrap();
+        let body: serde_json::Value = client.get(next).send().await.json().await;
+        assert_eq!(item_ids(&body), [3, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_list_handler_rejects_invalid_queries() {
+        let client = TestApp::builder().build();
+
+        for query in ["limit=0", "sort=name", "cursor=zz", "colour=red"] {
+            let res = client.get(&format!("/data?{query}")).send().await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{query}");
+            assert_eq!(res.headers()["content-type"], crate::problem::PROBLEM_JSON);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mocked_update_handlers() {
+        let client = mocked_crud_app(Ok(data(50)));
+
+        let res = client.put("/data/50").json(&serde_json::json!({"name": "fifty"})).send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: Response = res.json().await;
+        assert_eq!(body.id, 50);
+
+        let res = client.patch("/data/50").json(&serde_json::json!({"name": "fifty"})).send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: Response = res.json().await;
+        assert_eq!(body.id, 50);
+    }
+
+    #[tokio::test]
+    async fn test_update_handler_passes_the_path_id() {
+        let repo = MockDataRepo::new()
+            .reply_for(7, Reply::ok(data(7)))
+            .reply_for(7, Reply::err(DataRepoError::Conflict));
+        let client = TestApp::builder().with_data_repo(repo.clone()).build();
+
+        let res = client.put("/data/7").json(&serde_json::json!({"name": "seven"})).send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = client.put("/data/7").json(&serde_json::json!({"name": "seven"})).send().await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        repo.assert_call_count(2);
+        assert!(matches!(repo.calls()[0], DataRepoCall::Update(7, _, None)));
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests_use_the_record_revision() {
+        let record = data(7);
+        let first_tag = entity_tag(&record);
+        let repo: InMemoryDataRepo = [record].into_iter().collect();
+        let client = TestApp::builder().with_data_repo(repo).build();
+
+        let res = client.get("/data/7").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["etag"], first_tag.as_str());
+
+        let res = client.get("/data/7").header("if-none-match", &first_tag).send().await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()["etag"], first_tag.as_str());
+        assert!(res.bytes().await.is_empty());
+
+        let update = serde_json::json!({"name": "seven"});
+        let res = client.put("/data/7").header("if-match", &first_tag).json(&update).send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let second_tag = res.headers()["etag"].to_str().unwrap().to_string();
+        assert!(second_tag.starts_with("\"2-"));
+
+        // A writer still holding the first version must not overwrite the second
+        let res = client.put("/data/7").header("if-match", &first_tag).json(&update).send().await;
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(res.headers()["content-type"], crate::problem::PROBLEM_JSON);
+
+        let res = client.get("/data/7").header("if-none-match", &first_tag).send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // Nor may it overwrite a record created again under the same id, though that is back at
+        // version 1
+        assert_eq!(client.delete("/data/7").send().await.status(), StatusCode::NO_CONTENT);
+        assert_eq!(client.post("/data").json(&data_body(7)).send().await.status(), StatusCode::CREATED);
+
+        let res = client.put("/data/7").header("if-match", &first_tag).json(&update).send().await;
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+        let res = client.get("/data/7").header("if-none-match", &first_tag).send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_update_handler_passes_the_expected_revision() {
+        let repo = MockDataRepo::new().reply(Reply::ok(data(7)));
+        let client = TestApp::builder().with_data_repo(repo.clone()).build();
+
+        let mut record = data(7);
+        record.version = 4;
+        let tag = entity_tag(&record);
+
+        let update = serde_json::json!({"name": "seven"});
+        client.put("/data/7").header("if-match", &tag).json(&update).send().await;
+        client.put("/data/7").header("if-match", "*").json(&update).send().await;
+
+        let res = client.put("/data/7").header("if-match", format!("W/{tag}")).json(&update).send().await;
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+        repo.assert_call_count(2);
+        let calls = repo.calls();
+        assert!(matches!(&calls[0], DataRepoCall::Update(7, _, Some(revision)) if *revision == record.revision()));
+        assert!(matches!(calls[1], DataRepoCall::Update(7, _, None)));
+    }
+
+    #[tokio::test]
+    async fn test_patch_merges_onto_the_stored_record() {
+        let mut record = data(7);
+        record.tags = vec!["red".to_string()];
+        record.payload = serde_json::json!({"weight": 1});
+        let first_tag = entity_tag(&record);
+        let repo: InMemoryDataRepo = [record].into_iter().collect();
+        let client = TestApp::builder().with_data_repo(repo).build();
+
+        let res = client.patch("/data/7").json(&serde_json::json!({"name": "seven"})).send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: serde_json::Value = res.json().await;
+        assert_eq!(body["name"], "seven");
+        assert_eq!(body["tags"], serde_json::json!(["red"]));
+        assert_eq!(body["payload"], serde_json::json!({"weight": 1}));
+        assert_eq!(body["version"], 2);
+
+        // The patch is merged onto the revision the client named, which is no longer stored
+        let res = client
+            .patch("/data/7")
+            .header("if-match", &first_tag)
+            .json(&serde_json::json!({"tags": []}))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+        let res = client.patch("/data/7").json(&serde_json::json!({"name": ""})).send().await;
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let res = client.patch("/data/8").json(&serde_json::json!({"name": "eight"})).send().await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let body: serde_json::Value = client.get("/data/7").send().await.json().await;
+        assert_eq!(body["tags"], serde_json::json!(["red"]));
+        assert_eq!(body["version"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_patch_without_if_match_retries_a_lost_race() {
+        let original = data(7);
+        let mut moved_on = original.clone();
+        moved_on.version = 2;
+
+        let repo = MockDataRepo::new()
+            .reply_for(7, Reply::ok(original.clone()))
+            .reply_for(7, Reply::err(DataRepoError::PreconditionFailed))
+            .reply_for(7, Reply::ok(moved_on.clone()))
+            .reply_for(7, Reply::ok(moved_on.clone()));
+        let client = TestApp::builder().with_data_repo(repo.clone()).build();
+
+        let res = client.patch("/data/7").json(&serde_json::json!({"name": "seven"})).send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let calls = repo.calls();
+        assert_eq!(calls.len(), 4);
+        assert!(matches!(&calls[1], DataRepoCall::Update(7, _, Some(revision)) if *revision == original.revision()));
+        assert!(matches!(&calls[3], DataRepoCall::Update(7, patched, Some(revision))
+            if *revision == moved_on.revision() && patched.name == "seven"));
+    }
+
+    #[tokio::test]
+    async fn test_mocked_update_handler_not_found() {
+        let client = mocked_crud_app(Err(DataRepoError::NotFound));
+
+        let res = client.put("/data/50").json(&serde_json::json!({"name": "fifty"})).send().await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_mocked_delete_handler() {
+        let client = mocked_crud_app(Ok(data(50)));
+
+        let res = client.delete("/data/50").send().await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let client = mocked_crud_app(Err(DataRepoError::NotFound));
+
+        let res = client.delete("/data/50").send().await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_overridden_services_back_the_real_routes() {
+        let metrics = Arc::new(Metrics::default());
+        let client = TestApp::builder().with_service(metrics.clone()).build();
+
+        let res = client.get("/data").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        assert!(metrics.render().unwrap().contains(r#"route="/data""#));
+    }
+
+    #[tokio::test]
+    async fn test_mocked_extract_handler() {
+        let client = TestApp::builder().with_data_repo(MockDataRepo::new()).build();
+
+        let res = client.get("/pot/50").send().await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.headers()["content-type"], crate::problem::PROBLEM_JSON);
+
+        let body: serde_json::Value = res.json().await;
+        assert_eq!(body["status"], 404);
+        assert_eq!(body["title"], "Not Found");
+    }
+
+    #[derive(Debug)]
+    struct BackendFailure;
+
+    impl std::fmt::Display for BackendFailure {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("secret connection string leaked")
+        }
+    }
+
+    impl std::error::Error for BackendFailure {}
+
+    #[tokio::test]
+    async fn test_data_repo_error_statuses() {
+        let cases = [
+            (DataRepoError::NotFound, StatusCode::NOT_FOUND),
+            (DataRepoError::InvalidRequest, StatusCode::BAD_REQUEST),
+            (DataRepoError::Conflict, StatusCode::CONFLICT),
+            (DataRepoError::PreconditionFailed, StatusCode::PRECONDITION_FAILED),
+            (DataRepoError::Unavailable, StatusCode::SERVICE_UNAVAILABLE),
+            (DataRepoError::Timeout, StatusCode::GATEWAY_TIMEOUT),
+            (DataRepoError::internal(BackendFailure), StatusCode::INTERNAL_SERVER_ERROR),
+        ];
+
+        for (err, status) in cases {
+            let client = mocked_crud_app(Err(err));
+
+            let res = client.get("/data/50").send().await;
+            assert_eq!(res.status(), status);
+            assert_eq!(res.headers()["content-type"], crate::problem::PROBLEM_JSON);
+
+            let body: serde_json::Value = res.json().await;
+            assert_eq!(body["status"], status.as_u16());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_internal_errors_hide_their_source() {
+        let err = DataRepoError::internal(BackendFailure);
+        assert!(std::error::Error::source(&err).is_some());
+
+        let client = mocked_crud_app(Err(err));
+
+        let res = client.get("/data/50").send().await;
+        let body = res.text().await;
+        assert!(!body.contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown_drains_in_flight_requests() {
+        let repo = MockDataRepo::new().reply(Reply::ok(data(7)).after(Duration::from_millis(200)));
+        let app_state = AppState::new(Arc::new(repo.clone())).with_policy(Arc::new(AllowAll));
+
+        let listener = std::net::TcpListener::bind("[::1]:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+
+        let server = tokio::spawn(async move {
+            let config = Config::default();
+            serve(listener, &config, app_state, async {
+                let _ = shutdown_rx.await;
+            })
+            .await
+        });
+
+        let request = tokio::spawn(reqwest::get(format!("http://{addr}/data/7")));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown_tx.send(()).unwrap();
+
+        let res = request.await.unwrap().unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        server.await.unwrap().unwrap();
+        assert!(repo.is_shut_down());
+    }
+
+    #[tokio::test]
+    async fn test_serve_refuses_to_start_with_missing_services() {
+        let app_state = AppState {
+            container: Arc::new(Container::default()),
+        };
+
+        let listener = std::net::TcpListener::bind("[::1]:0").unwrap();
+        let err = serve(listener, &Config::default(), app_state, std::future::pending())
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("DataRepo"));
+    }
+
+    /// Registers each of [`AppState::required_services`] on its own, so the tests below can leave
+    /// any one of them out.
+    fn required_registrations() -> [(&'static str, fn(&mut Container)); 6] {
+        [
+            ("Authenticator", |container| {
+                container.register::<dyn Authenticator + Send + Sync>(Arc::new(ChainAuthenticator::default()));
+            }),
+            ("AuthorizationPolicy", |container| {
+                container.register::<dyn AuthorizationPolicy + Send + Sync>(Arc::new(AllowAll));
+            }),
+            ("DataRepo", |container| {
+                container.register::<dyn DataRepo + Send + Sync>(Arc::new(InMemoryDataRepo::default()));
+            }),
+            ("Metrics", |container| {
+                container.register(Arc::new(Metrics::default()));
+            }),
+            ("IdempotencyStore", |container| {
+                container.register::<dyn IdempotencyStore + Send + Sync>(Arc::new(InMemoryIdempotencyStore::default()));
+            }),
+            ("AuditTrail", |container| {
+                container.register_scoped::<AuditTrail, _>(AuditTrailFactory);
+            }),
+        ]
+    }
+
+    fn app_state_with(registrations: impl IntoIterator<Item = fn(&mut Container)>) -> AppState {
+        let mut container = Container::default();
+        for register in registrations {
+            register(&mut container);
+        }
+
+        AppState {
+            container: Arc::new(container),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_app_names_each_missing_service() {
+        let registrations = required_registrations();
+
+        for (skipped, (name, _)) in registrations.iter().enumerate() {
+            let app_state = app_state_with(
+                registrations
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| *index != skipped)
+                    .map(|(_, (_, register))| *register),
+            );
+
+            assert!(app_state.verify().is_err(), "verify accepted a state without {name}");
+
+            let err = build_app(app_state, &Config::default()).unwrap_err();
+            assert!(err.to_string().contains(name), "{err} doesn't name {name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_required_services_are_enough_to_serve_every_route() {
+        let app_state = app_state_with(required_registrations().map(|(_, register)| register));
+        let client = TestClient::new(build_app(app_state, &Config::default()).unwrap());
+
+        let requests = [
+            client.get("/"),
+            client.get("/healthz"),
+            client.get("/readyz"),
+            client.get("/me"),
+            client.post("/data").json(&data_body(1)),
+            client.get("/data"),
+            client.post("/data/batch").json(&serde_json::json!({"ids": [1]})),
+            client.get("/data/1"),
+            client.put("/data/1").json(&data_body(1)),
+            client.patch("/data/1").json(&data_body(1)),
+            client.get("/pot/1"),
+            client.delete("/data/1"),
+            client.get("/metrics"),
+        ];
+
+        for request in requests {
+            let res = request.send().await;
+            assert_ne!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prod_repo_applies_the_update_body() {
+        let mut input = DataInput::new("renamed");
+        input.tags = vec!["a".to_string()];
+
+        let expected = ProdDataRepo::record(3).revision();
+        let updated = ProdDataRepo.update(3, Data::new(3, input), Some(expected)).await.unwrap();
+        assert_eq!((updated.id, updated.name.as_str(), updated.version), (3, "renamed", 2));
+        assert_eq!(updated.tags, vec!["a"]);
+
+        assert!(matches!(
+            ProdDataRepo.update(3, data(4), None).await,
+            Err(DataRepoError::InvalidRequest)
+        ));
+    }
+}
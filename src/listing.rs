@@ -0,0 +1,459 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Query};
+use http::request::Parts;
+use http::StatusCode;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::problem::Problem;
+use crate::Data;
+
+/// Page size used when a request doesn't ask for one.
+pub const DEFAULT_LIMIT: usize = 50;
+
+/// The largest page a request can ask for. Larger limits are capped rather than rejected.
+pub const MAX_LIMIT: usize = 200;
+
+/// Marks where the previous page ended. Clients should treat it as opaque and only pass back the
+/// value from `next_cursor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cursor {
+    after: usize,
+}
+
+impl Cursor {
+    /// The id of the last record on the previous page.
+    pub fn after(&self) -> usize {
+        self.after
+    }
+}
+
+impl Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:x}", self.after)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        usize::from_str_radix(value, 16).map(|after| Cursor { after })
+    }
+}
+
+impl Serialize for Cursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    IdAscending,
+    IdDescending,
+}
+
+impl SortOrder {
+    fn as_param(&self) -> &'static str {
+        match self {
+            SortOrder::IdAscending => "id",
+            SortOrder::IdDescending => "-id",
+        }
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "id" => Ok(SortOrder::IdAscending),
+            "-id" => Ok(SortOrder::IdDescending),
+            other => Err(format!("cannot sort by {other:?}, expected one of \"id\" or \"-id\"")),
+        }
+    }
+}
+
+/// Matches a record's name either exactly or by how it starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NameFilter {
+    Exact(String),
+    Prefix(String),
+}
+
+impl NameFilter {
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NameFilter::Exact(exact) => name == exact,
+            NameFilter::Prefix(prefix) => name.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// Restricts a listing to the records whose fields fall within the given bounds.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DataFilter {
+    pub min_id: Option<usize>,
+    pub max_id: Option<usize>,
+    pub name: Option<NameFilter>,
+    /// Only records carrying this tag, among any others.
+    pub tag: Option<String>,
+}
+
+impl DataFilter {
+    pub fn matches(&self, data: &Data) -> bool {
+        let above_min = match self.min_id {
+            Some(min_id) => data.id >= min_id,
+            None => true,
+        };
+        let below_max = match self.max_id {
+            Some(max_id) => data.id <= max_id,
+            None => true,
+        };
+        let named = match &self.name {
+            Some(name) => name.matches(&data.name),
+            None => true,
+        };
+        let tagged = match &self.tag {
+            Some(tag) => data.tags.contains(tag),
+            None => true,
+        };
+
+        above_min && below_max && named && tagged
+    }
+}
+
+/// A validated request for one page of records, handed to [`DataRepo::list`](crate::DataRepo::list).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListQuery {
+    pub cursor: Option<Cursor>,
+    pub filter: DataFilter,
+    pub limit: usize,
+    pub sort: SortOrder,
+}
+
+impl Default for ListQuery {
+    fn default() -> Self {
+        Self {
+            cursor: None,
+            filter: DataFilter::default(),
+            limit: DEFAULT_LIMIT,
+            sort: SortOrder::default(),
+        }
+    }
+}
+
+impl ListQuery {
+    /// Whether `data` passes the filter and comes after the cursor in the requested order.
+    pub fn matches(&self, data: &Data) -> bool {
+        let after_cursor = match (self.cursor, self.sort) {
+            (None, _) => true,
+            (Some(cursor), SortOrder::IdAscending) => data.id > cursor.after,
+            (Some(cursor), SortOrder::IdDescending) => data.id < cursor.after,
+        };
+
+        after_cursor && self.filter.matches(data)
+    }
+
+    /// Filters, sorts and pages an unordered set of records. Suited to backends that can't do
+    /// this work themselves.
+    pub fn paginate(&self, records: impl IntoIterator<Item = Data>) -> Page<Data> {
+        let mut items: Vec<Data> = records.into_iter().filter(|data| self.matches(data)).collect();
+
+        match self.sort {
+            SortOrder::IdAscending => items.sort_by_key(|data| data.id),
+            SortOrder::IdDescending => items.sort_by_key(|data| std::cmp::Reverse(data.id)),
+        }
+        items.truncate(self.limit + 1);
+
+        self.page(items)
+    }
+
+    /// Builds the page from records that already match and are in order. Backends should fetch
+    /// one record more than the limit so the presence of a next page can be detected.
+    pub fn page(&self, mut items: Vec<Data>) -> Page<Data> {
+        let next_cursor = if items.len() > self.limit {
+            items.truncate(self.limit);
+            items.last().map(|data| Cursor { after: data.id })
+        } else {
+            None
+        };
+
+        Page { items, next_cursor }
+    }
+
+    /// The query string requesting the page that starts at `cursor`, keeping everything else.
+    fn next_query(&self, cursor: Cursor) -> String {
+        let mut query = format!("cursor={cursor}&limit={}&sort={}", self.limit, self.sort.as_param());
+
+        if let Some(min_id) = self.filter.min_id {
+            query.push_str(&format!("&min_id={min_id}"));
+        }
+
+        if let Some(max_id) = self.filter.max_id {
+            query.push_str(&format!("&max_id={max_id}"));
+        }
+
+        match &self.filter.name {
+            Some(NameFilter::Exact(name)) => query.push_str(&format!("&name={}", encode(name))),
+            Some(NameFilter::Prefix(prefix)) => query.push_str(&format!("&name_prefix={}", encode(prefix))),
+            None => {}
+        }
+
+        if let Some(tag) = &self.filter.tag {
+            query.push_str(&format!("&tag={}", encode(tag)));
+        }
+
+        query
+    }
+}
+
+fn encode(value: &str) -> String {
+    form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListParams {
+    cursor: Option<String>,
+    limit: Option<usize>,
+    min_id: Option<usize>,
+    max_id: Option<usize>,
+    name: Option<String>,
+    name_prefix: Option<String>,
+    tag: Option<String>,
+    sort: Option<String>,
+}
+
+impl TryFrom<ListParams> for ListQuery {
+    type Error = String;
+
+    fn try_from(params: ListParams) -> Result<Self, Self::Error> {
+        let cursor = params
+            .cursor
+            .map(|cursor| cursor.parse().map_err(|_| format!("invalid cursor {cursor:?}")))
+            .transpose()?;
+
+        let limit = match params.limit {
+            Some(0) => return Err("limit must be at least 1".to_string()),
+            Some(limit) => limit.min(MAX_LIMIT),
+            None => DEFAULT_LIMIT,
+        };
+
+        let sort = params.sort.as_deref().map(str::parse).transpose()?.unwrap_or_default();
+
+        let name = match (params.name, params.name_prefix) {
+            (Some(_), Some(_)) => return Err("name and name_prefix cannot be combined".to_string()),
+            (Some(name), None) => Some(NameFilter::Exact(name)),
+            (None, Some(prefix)) => Some(NameFilter::Prefix(prefix)),
+            (None, None) => None,
+        };
+
+        Ok(Self {
+            cursor,
+            filter: DataFilter {
+                min_id: params.min_id,
+                max_id: params.max_id,
+                name,
+                tag: params.tag,
+            },
+            limit,
+            sort,
+        })
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ListQuery {
+    type Rejection = Problem;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<ListParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| Problem::new(StatusCode::BAD_REQUEST).with_detail(rejection.body_text()))?;
+
+        ListQuery::try_from(params).map_err(|reason| Problem::new(StatusCode::BAD_REQUEST).with_detail(reason))
+    }
+}
+
+/// One page of a listing. `next_cursor` is only present when there are more records to fetch.
+#[derive(Clone, Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<Cursor>,
+}
+
+/// A page as returned to clients, with a link to the next page.
+#[derive(Debug, Serialize)]
+pub struct PageResponse<T> {
+    #[serde(flatten)]
+    page: Page<T>,
+    links: PageLinks,
+}
+
+#[derive(Debug, Serialize)]
+struct PageLinks {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next: Option<String>,
+}
+
+impl<T> PageResponse<T> {
+    pub(crate) fn new(path: &str, query: &ListQuery, page: Page<T>) -> Self {
+        let next = page.next_cursor.map(|cursor| format!("{path}?{}", query.next_query(cursor)));
+
+        Self {
+            page,
+            links: PageLinks { next },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::data;
+
+    fn records() -> Vec<Data> {
+        (1..=7).rev().map(data).collect()
+    }
+
+    fn ids(page: &Page<Data>) -> Vec<usize> {
+        page.items.iter().map(|data| data.id).collect()
+    }
+
+    #[test]
+    fn test_cursor_walks_every_record_once() {
+        let mut query = ListQuery {
+            limit: 3,
+            ..ListQuery::default()
+        };
+
+        let page = query.paginate(records());
+        assert_eq!(ids(&page), vec![1, 2, 3]);
+
+        query.cursor = page.next_cursor;
+        let page = query.paginate(records());
+        assert_eq!(ids(&page), vec![4, 5, 6]);
+
+        query.cursor = page.next_cursor;
+        let page = query.paginate(records());
+        assert_eq!(ids(&page), vec![7]);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_filters_and_descending_order() {
+        let query = ListQuery {
+            filter: DataFilter {
+                min_id: Some(2),
+                max_id: Some(5),
+                ..DataFilter::default()
+            },
+            limit: 2,
+            sort: SortOrder::IdDescending,
+            ..ListQuery::default()
+        };
+
+        let page = query.paginate(records());
+        assert_eq!(ids(&page), vec![5, 4]);
+
+        let next = ListQuery {
+            cursor: page.next_cursor,
+            ..query
+        };
+        assert_eq!(ids(&next.paginate(records())), vec![3, 2]);
+    }
+
+    #[test]
+    fn test_params_are_validated_and_capped() {
+        let params = |query: &str| {
+            let uri = format!("/data?{query}").parse().unwrap();
+            Query::<ListParams>::try_from_uri(&uri).unwrap().0
+        };
+
+        let query = ListQuery::try_from(params("limit=5000&sort=-id&min_id=3")).unwrap();
+        assert_eq!(query.limit, MAX_LIMIT);
+        assert_eq!(query.sort, SortOrder::IdDescending);
+        assert_eq!(query.filter.min_id, Some(3));
+
+        let query = ListQuery::try_from(params("name_prefix=rec&tag=red")).unwrap();
+        assert_eq!(query.filter.name, Some(NameFilter::Prefix("rec".to_string())));
+        assert_eq!(query.filter.tag.as_deref(), Some("red"));
+
+        assert!(ListQuery::try_from(params("limit=0")).is_err());
+        assert!(ListQuery::try_from(params("name=a&name_prefix=b")).is_err());
+        assert!(ListQuery::try_from(params("sort=name")).is_err());
+        assert!(ListQuery::try_from(params("cursor=not-a-cursor")).is_err());
+    }
+
+    #[test]
+    fn test_next_link_keeps_the_query() {
+        let query = ListQuery {
+            filter: DataFilter {
+                min_id: Some(2),
+                ..DataFilter::default()
+            },
+            limit: 1,
+            ..ListQuery::default()
+        };
+
+        let response = PageResponse::new("/data", &query, query.paginate(records()));
+        assert_eq!(
+            response.links.next.as_deref(),
+            Some("/data?cursor=2&limit=1&sort=id&min_id=2")
+        );
+
+        let query = ListQuery {
+            filter: DataFilter {
+                name: Some(NameFilter::Exact("record 1".to_string())),
+                tag: Some("a&b".to_string()),
+                ..DataFilter::default()
+            },
+            limit: 1,
+            ..ListQuery::default()
+        };
+
+        let response = PageResponse::new("/data", &query, query.page(vec![data(1), data(2)]));
+        assert_eq!(
+            response.links.next.as_deref(),
+            Some("/data?cursor=1&limit=1&sort=id&name=record+1&tag=a%26b")
+        );
+    }
+
+    #[test]
+    fn test_name_and_tag_filters() {
+        let tagged = |id: usize, tags: &[&str]| Data {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..data(id)
+        };
+        let records = vec![tagged(1, &["red"]), tagged(2, &["red", "blue"]), tagged(10, &["blue"])];
+
+        let query = |filter: DataFilter| ListQuery {
+            filter,
+            ..ListQuery::default()
+        };
+
+        let exact = query(DataFilter {
+            name: Some(NameFilter::Exact("record 1".to_string())),
+            ..DataFilter::default()
+        });
+        assert_eq!(ids(&exact.paginate(records.clone())), vec![1]);
+
+        let prefix = query(DataFilter {
+            name: Some(NameFilter::Prefix("record 1".to_string())),
+            ..DataFilter::default()
+        });
+        assert_eq!(ids(&prefix.paginate(records.clone())), vec![1, 10]);
+
+        let tag = query(DataFilter {
+            tag: Some("blue".to_string()),
+            ..DataFilter::default()
+        });
+        assert_eq!(ids(&tag.paginate(records)), vec![2, 10]);
+    }
+}
@@ -0,0 +1,203 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::{MatchedPath, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http::header::CONTENT_TYPE;
+use http::{Request, StatusCode};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+
+use crate::container::Resolved;
+use crate::problem::Problem;
+
+/// Prometheus collectors for the HTTP layer and the injected data repo, all registered in a
+/// registry owned by this struct rather than the global default one so tests stay isolated.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    http_requests_in_flight: IntGaugeVec,
+    repo_calls: IntCounterVec,
+    repo_call_duration: HistogramVec,
+    cache_lookups: IntCounterVec,
+}
+
+impl Metrics {
+    pub(crate) fn record_repo_call(&self, operation: &str, outcome: &str, elapsed: Duration) {
+        self.repo_calls.with_label_values(&[operation, outcome]).inc();
+        self.repo_call_duration
+            .with_label_values(&[operation])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Counts a lookup in a [`CachedDataRepo`](crate::CachedDataRepo) by its `result`: `hit`,
+    /// `negative_hit` for a record cached as missing, or `miss`.
+    pub(crate) fn record_cache_lookup(&self, result: &str) {
+        self.cache_lookups.with_label_values(&[result]).inc();
+    }
+
+    pub(crate) fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+        String::from_utf8(buffer).map_err(|err| prometheus::Error::Msg(err.to_string()))
+    }
+
+    fn register<C>(registry: &Registry, collector: C) -> C
+    where
+        C: prometheus::core::Collector + Clone + 'static,
+    {
+        registry
+            .register(Box::new(collector.clone()))
+            .expect("metric names to be unique");
+        collector
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Total HTTP requests handled"),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric definition");
+
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency"),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric definition");
+
+        let http_requests_in_flight = IntGaugeVec::new(
+            Opts::new("http_requests_in_flight", "HTTP requests currently being handled"),
+            &["method", "route"],
+        )
+        .expect("valid metric definition");
+
+        let repo_calls = IntCounterVec::new(
+            Opts::new("data_repo_calls_total", "Total calls made to the data repo"),
+            &["operation", "outcome"],
+        )
+        .expect("valid metric definition");
+
+        let repo_call_duration = HistogramVec::new(
+            HistogramOpts::new("data_repo_call_duration_seconds", "Data repo call latency"),
+            &["operation"],
+        )
+        .expect("valid metric definition");
+
+        let cache_lookups = IntCounterVec::new(
+            Opts::new("data_repo_cache_lookups_total", "Record lookups answered by the data repo cache"),
+            &["result"],
+        )
+        .expect("valid metric definition");
+
+        Self {
+            http_requests: Self::register(&registry, http_requests),
+            http_request_duration: Self::register(&registry, http_request_duration),
+            http_requests_in_flight: Self::register(&registry, http_requests_in_flight),
+            repo_calls: Self::register(&registry, repo_calls),
+            repo_call_duration: Self::register(&registry, repo_call_duration),
+            cache_lookups: Self::register(&registry, cache_lookups),
+            registry,
+        }
+    }
+}
+
+/// Decrements the in-flight gauge even when the request future is dropped early, such as when a
+/// timeout fires.
+struct InFlightGuard(IntGauge);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Records request metrics labelled by the matched route template, so `/data/1` and `/data/2`
+/// share a series. Must be installed with `route_layer` for the matched path to be available.
+pub(crate) async fn track_http_metrics<B>(
+    State(metrics): State<Arc<Metrics>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+    let method = request.method().to_string();
+
+    let in_flight = metrics
+        .http_requests_in_flight
+        .with_label_values(&[method.as_str(), route.as_str()]);
+    in_flight.inc();
+    let _guard = InFlightGuard(in_flight);
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let status = response.status().as_u16().to_string();
+
+    metrics
+        .http_requests
+        .with_label_values(&[method.as_str(), route.as_str(), status.as_str()])
+        .inc();
+    metrics
+        .http_request_duration
+        .with_label_values(&[method.as_str(), route.as_str(), status.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+
+    response
+}
+
+pub async fn metrics_handler(Resolved(metrics): Resolved<Metrics>) -> Response {
+    match metrics.render() {
+        Ok(body) => ([(CONTENT_TYPE, TextEncoder::new().format_type().to_owned())], body).into_response(),
+        Err(err) => {
+            tracing::error!(error = %err, "failed to render metrics");
+            Problem::new(StatusCode::INTERNAL_SERVER_ERROR).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+    use crate::test_helpers::*;
+
+    use axum::middleware::from_fn_with_state;
+    use axum::routing::get;
+    use axum::Router;
+
+    #[tokio::test]
+    async fn test_requests_are_labelled_by_route() {
+        let metrics = Arc::new(Metrics::default());
+
+        let mut container = Container::default();
+        container.register(metrics.clone());
+
+        let app = Router::new()
+            .route("/items/:id", get(|| async { "item" }))
+            .route("/metrics", get(metrics_handler))
+            .route_layer(from_fn_with_state(metrics, track_http_metrics))
+            .with_state(Arc::new(container));
+
+        let client = TestClient::new(app);
+
+        assert_eq!(client.get("/items/1").send().await.status(), StatusCode::OK);
+        assert_eq!(client.get("/items/2").send().await.status(), StatusCode::OK);
+
+        let res = client.get("/metrics").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+
+        let body = res.text().await;
+        assert!(body.contains(r#"http_requests_total{method="GET",route="/items/:id",status="200"} 2"#));
+        assert!(body.contains(r#"http_requests_in_flight{method="GET",route="/items/:id"} 0"#));
+        assert!(body.contains("http_request_duration_seconds_bucket"));
+    }
+}
@@ -0,0 +1,224 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use http::request::Parts;
+use http::{Request, StatusCode};
+use tokio::sync::Mutex;
+
+use crate::container::Container;
+use crate::problem::Problem;
+
+/// How the request a scoped service was created for ended. Responses with a 4xx or 5xx status
+/// roll back, everything else commits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScopeOutcome {
+    Committed,
+    RolledBack,
+}
+
+/// Creates the per-request instance of a scoped service `T`. Registered in the [`Container`]
+/// with [`Container::register_scoped`], and invoked lazily the first time a handler in a request
+/// extracts [`Scoped<T>`].
+#[async_trait]
+pub trait ScopedFactory<T: ?Sized + Send + Sync>: Send + Sync {
+    async fn create(&self, parts: &Parts) -> Result<Arc<T>, Response>;
+
+    /// Called with the instance created for a request once its response has been produced.
+    async fn complete(&self, _service: Arc<T>, _outcome: ScopeOutcome) {}
+}
+
+type Completion = Box<dyn FnOnce(ScopeOutcome) -> BoxFuture<'static, ()> + Send>;
+
+#[derive(Default)]
+struct ScopeState {
+    services: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+    completions: Vec<Completion>,
+}
+
+/// The scoped services created so far for a single request, stored in its extensions.
+#[derive(Clone, Default)]
+struct RequestScope(Arc<Mutex<ScopeState>>);
+
+impl RequestScope {
+    async fn complete(&self, outcome: ScopeOutcome) {
+        let ScopeState { services, completions } = std::mem::take(&mut *self.0.lock().await);
+
+        for completion in completions {
+            completion(outcome).await;
+        }
+
+        // Release the request's instances now rather than whenever the extensions are dropped
+        drop(services);
+    }
+}
+
+/// Opens a scope for each request and completes every scoped service created within it once
+/// the inner service has produced a response. Requests that are cancelled never complete, so
+/// their scoped services are simply dropped.
+pub(crate) async fn request_scope<B>(mut request: Request<B>, next: Next<B>) -> Response {
+    let scope = RequestScope::default();
+    request.extensions_mut().insert(scope.clone());
+
+    let response = next.run(request).await;
+
+    let status = response.status();
+    let outcome = if status.is_client_error() || status.is_server_error() {
+        ScopeOutcome::RolledBack
+    } else {
+        ScopeOutcome::Committed
+    };
+    scope.complete(outcome).await;
+
+    response
+}
+
+/// Extracts the request-scoped instance of `T`, creating it on first use. Every extractor in the
+/// same request receives the same instance.
+pub struct Scoped<T: ?Sized>(pub Arc<T>);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for Scoped<T>
+where
+    Arc<Container>: FromRef<S>,
+    S: Send + Sync,
+    T: ?Sized + Send + Sync + 'static,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let scope = parts.extensions.get::<RequestScope>().cloned().ok_or_else(|| {
+            tracing::error!("scoped service requested without the request_scope middleware");
+            Problem::new(StatusCode::INTERNAL_SERVER_ERROR).into_response()
+        })?;
+
+        let key = TypeId::of::<Arc<T>>();
+        let mut scoped = scope.0.lock().await;
+
+        if let Some(service) = scoped
+            .services
+            .get(&key)
+            .and_then(|service| service.downcast_ref::<Arc<T>>())
+        {
+            return Ok(Scoped(service.clone()));
+        }
+
+        let factory = Arc::<Container>::from_ref(state)
+            .resolve::<dyn ScopedFactory<T>>()
+            .map_err(IntoResponse::into_response)?;
+        let service = factory.create(parts).await?;
+
+        scoped.services.insert(key, Arc::new(service.clone()));
+
+        let completed = service.clone();
+        scoped
+            .completions
+            .push(Box::new(move |outcome| async move { factory.complete(completed, outcome).await }.boxed()));
+
+        Ok(Scoped(service))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::middleware::from_fn;
+    use axum::routing::get;
+    use axum::Router;
+
+    struct UnitOfWork {
+        number: usize,
+    }
+
+    #[derive(Clone, Default)]
+    struct UnitOfWorkFactory {
+        created: Arc<AtomicUsize>,
+        outcomes: Arc<std::sync::Mutex<Vec<(usize, ScopeOutcome)>>>,
+    }
+
+    #[async_trait]
+    impl ScopedFactory<UnitOfWork> for UnitOfWorkFactory {
+        async fn create(&self, _parts: &Parts) -> Result<Arc<UnitOfWork>, Response> {
+            let number = self.created.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(Arc::new(UnitOfWork { number }))
+        }
+
+        async fn complete(&self, service: Arc<UnitOfWork>, outcome: ScopeOutcome) {
+            self.outcomes.lock().unwrap().push((service.number, outcome));
+        }
+    }
+
+    async fn shared_handler(Scoped(first): Scoped<UnitOfWork>, Scoped(second): Scoped<UnitOfWork>) -> String {
+        assert!(Arc::ptr_eq(&first, &second));
+        first.number.to_string()
+    }
+
+    async fn failing_handler(Scoped(work): Scoped<UnitOfWork>) -> (StatusCode, String) {
+        (StatusCode::CONFLICT, work.number.to_string())
+    }
+
+    fn scoped_app(factory: UnitOfWorkFactory) -> Router {
+        let mut container = Container::default();
+        container.register_scoped::<UnitOfWork, _>(factory);
+
+        Router::new()
+            .route("/ok", get(shared_handler))
+            .route("/fail", get(failing_handler))
+            .route("/unused", get(|| async { "nothing scoped" }))
+            .layer(from_fn(request_scope))
+            .with_state(Arc::new(container))
+    }
+
+    #[tokio::test]
+    async fn test_one_instance_per_request() {
+        let factory = UnitOfWorkFactory::default();
+        let client = TestClient::new(scoped_app(factory.clone()));
+
+        assert_eq!(client.get("/ok").send().await.text().await, "1");
+        assert_eq!(client.get("/ok").send().await.text().await, "2");
+
+        // Nothing is created for requests that never ask for the service
+        client.get("/unused").send().await;
+        assert_eq!(factory.created.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_completion_follows_response_status() {
+        let factory = UnitOfWorkFactory::default();
+        let client = TestClient::new(scoped_app(factory.clone()));
+
+        assert_eq!(client.get("/ok").send().await.status(), StatusCode::OK);
+        assert_eq!(client.get("/fail").send().await.status(), StatusCode::CONFLICT);
+
+        let outcomes = factory.outcomes.lock().unwrap().clone();
+        assert_eq!(
+            outcomes,
+            vec![(1, ScopeOutcome::Committed), (2, ScopeOutcome::RolledBack)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_requires_the_scope_middleware() {
+        let mut container = Container::default();
+        container.register_scoped::<UnitOfWork, _>(UnitOfWorkFactory::default());
+
+        let app = Router::new()
+            .route("/ok", get(shared_handler))
+            .with_state(Arc::new(container));
+
+        let client = TestClient::new(app);
+
+        let res = client.get("/ok").send().await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
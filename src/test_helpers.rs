@@ -1,3 +1,11 @@
+mod fake_authenticator;
+mod fixtures;
+mod mock_data_repo;
+mod test_app;
 mod test_client;
 
+pub(crate) use fake_authenticator::*;
+pub(crate) use fixtures::*;
+pub(crate) use mock_data_repo::*;
+pub(crate) use test_app::*;
 pub(crate) use test_client::*;
@@ -0,0 +1,27 @@
+#![allow(dead_code)]
+use std::collections::HashMap;
+
+use axum::async_trait;
+
+use crate::auth::{AuthError, Authenticator, User};
+
+/// An [`Authenticator`] for tests that takes the bearer token to be the id of the user making the
+/// request, so a test can act as any user it has added without minting real tokens.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FakeAuthenticator {
+    users: HashMap<String, User>,
+}
+
+impl FakeAuthenticator {
+    pub(crate) fn with_user(mut self, user: User) -> Self {
+        self.users.insert(user.id.clone(), user);
+        self
+    }
+}
+
+#[async_trait]
+impl Authenticator for FakeAuthenticator {
+    async fn authenticate(&self, token: &str) -> Result<User, AuthError> {
+        self.users.get(token).cloned().ok_or(AuthError::InvalidCredentials)
+    }
+}
@@ -0,0 +1,12 @@
+#![allow(dead_code)]
+use crate::{Data, DataInput};
+
+/// A record with `id` and an otherwise unremarkable set of fields.
+pub(crate) fn data(id: usize) -> Data {
+    Data::new(id, DataInput::new(format!("record {id}")))
+}
+
+/// A request body that creates or replaces a record with the same fields as [`data`].
+pub(crate) fn data_body(id: usize) -> serde_json::Value {
+    serde_json::json!({"id": id, "name": format!("record {id}")})
+}
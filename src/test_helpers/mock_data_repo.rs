@@ -0,0 +1,275 @@
+#![allow(dead_code)]
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::async_trait;
+
+use crate::health::HealthStatus;
+use crate::listing::{ListQuery, Page};
+use crate::{Data, DataRepo, DataRepoError, Revision};
+
+/// A call received by a [`MockDataRepo`], with its arguments.
+#[derive(Clone, Debug)]
+pub(crate) enum DataRepoCall {
+    Create(Data),
+    Retrieve(usize),
+    Update(usize, Data, Option<Revision>),
+    Delete(usize),
+    List(ListQuery),
+}
+
+impl DataRepoCall {
+    /// The id of the record the call addressed, if it addressed one.
+    pub(crate) fn id(&self) -> Option<usize> {
+        match self {
+            DataRepoCall::Create(data) => Some(data.id),
+            DataRepoCall::Retrieve(id) | DataRepoCall::Update(id, _, _) | DataRepoCall::Delete(id) => Some(*id),
+            DataRepoCall::List(_) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Outcome {
+    Return(Result<Data, DataRepoError>),
+    Panic(String),
+}
+
+/// A scripted response for a [`MockDataRepo`] call. `list` wraps a returned record in a single
+/// element page and `delete` discards it.
+#[derive(Clone, Debug)]
+pub(crate) struct Reply {
+    outcome: Outcome,
+    delay: Option<Duration>,
+}
+
+impl Reply {
+    pub(crate) fn ok(data: Data) -> Self {
+        Ok(data).into()
+    }
+
+    pub(crate) fn err(err: DataRepoError) -> Self {
+        Err(err).into()
+    }
+
+    pub(crate) fn panic(message: impl Into<String>) -> Self {
+        Self {
+            outcome: Outcome::Panic(message.into()),
+            delay: None,
+        }
+    }
+
+    /// Waits for `delay` before replying.
+    pub(crate) fn after(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+impl From<Result<Data, DataRepoError>> for Reply {
+    fn from(result: Result<Data, DataRepoError>) -> Self {
+        Self {
+            outcome: Outcome::Return(result),
+            delay: None,
+        }
+    }
+}
+
+/// Replies queued for a set of calls. They are used in order, and the last one keeps answering
+/// once the rest have been used up.
+#[derive(Default)]
+struct Script(VecDeque<Reply>);
+
+impl Script {
+    fn next(&mut self) -> Option<Reply> {
+        if self.0.len() > 1 {
+            self.0.pop_front()
+        } else {
+            self.0.front().cloned()
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    calls: Vec<DataRepoCall>,
+    by_id: HashMap<usize, Script>,
+    fallback: Script,
+    health: Option<HealthStatus>,
+    shut_down: bool,
+}
+
+/// A [`DataRepo`] that records every call it receives and answers from a script. Calls for an id
+/// with replies queued through [`MockDataRepo::reply_for`] use those, everything else uses the
+/// replies queued through [`MockDataRepo::reply`], and calls with nothing scripted fail with
+/// [`DataRepoError::NotFound`].
+///
+/// Clones share their script and recorded calls, so a test can hand one clone to the app and make
+/// assertions on another.
+#[derive(Clone, Default)]
+pub(crate) struct MockDataRepo {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockDataRepo {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues `reply` for calls that don't address an id with its own script, including `list`.
+    pub(crate) fn reply(self, reply: impl Into<Reply>) -> Self {
+        self.state.lock().unwrap().fallback.0.push_back(reply.into());
+        self
+    }
+
+    /// Queues `reply` for calls addressing `id`.
+    pub(crate) fn reply_for(self, id: usize, reply: impl Into<Reply>) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .by_id
+            .entry(id)
+            .or_default()
+            .0
+            .push_back(reply.into());
+        self
+    }
+
+    pub(crate) fn with_health(self, status: HealthStatus) -> Self {
+        self.state.lock().unwrap().health = Some(status);
+        self
+    }
+
+    pub(crate) fn calls(&self) -> Vec<DataRepoCall> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    pub(crate) fn is_shut_down(&self) -> bool {
+        self.state.lock().unwrap().shut_down
+    }
+
+    #[track_caller]
+    pub(crate) fn assert_call_count(&self, expected: usize) {
+        let calls = self.calls();
+        assert_eq!(calls.len(), expected, "unexpected data repo calls: {calls:?}");
+    }
+
+    #[track_caller]
+    pub(crate) fn assert_called_with(&self, id: usize) {
+        let calls = self.calls();
+        assert!(
+            calls.iter().any(|call| call.id() == Some(id)),
+            "expected a data repo call for id {id}, got: {calls:?}"
+        );
+    }
+
+    async fn call(&self, call: DataRepoCall) -> Result<Data, DataRepoError> {
+        let reply = {
+            let mut state = self.state.lock().unwrap();
+            let id = call.id();
+            state.calls.push(call);
+
+            id.and_then(|id| state.by_id.get_mut(&id).and_then(Script::next))
+                .or_else(|| state.fallback.next())
+        };
+
+        let reply = match reply {
+            Some(reply) => reply,
+            None => return Err(DataRepoError::NotFound),
+        };
+
+        if let Some(delay) = reply.delay {
+            tokio::time::sleep(delay).await;
+        }
+
+        match reply.outcome {
+            Outcome::Return(result) => result,
+            Outcome::Panic(message) => panic!("{message}"),
+        }
+    }
+}
+
+#[async_trait]
+impl DataRepo for MockDataRepo {
+    async fn create(&self, data: Data) -> Result<Data, DataRepoError> {
+        self.call(DataRepoCall::Create(data)).await
+    }
+
+    async fn retrieve(&self, id: usize) -> Result<Data, DataRepoError> {
+        self.call(DataRepoCall::Retrieve(id)).await
+    }
+
+    async fn update(&self, id: usize, data: Data, expected: Option<Revision>) -> Result<Data, DataRepoError> {
+        self.call(DataRepoCall::Update(id, data, expected)).await
+    }
+
+    async fn delete(&self, id: usize) -> Result<(), DataRepoError> {
+        self.call(DataRepoCall::Delete(id)).await.map(|_| ())
+    }
+
+    async fn list(&self, query: &ListQuery) -> Result<Page<Data>, DataRepoError> {
+        self.call(DataRepoCall::List(query.clone())).await.map(|data| Page {
+            items: vec![data],
+            next_cursor: None,
+        })
+    }
+
+    async fn health_check(&self) -> HealthStatus {
+        self.state.lock().unwrap().health.clone().unwrap_or(HealthStatus::Healthy)
+    }
+
+    async fn shutdown(&self) {
+        self.state.lock().unwrap().shut_down = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::*;
+
+    use http::StatusCode;
+
+    #[tokio::test]
+    async fn test_scripted_replies() {
+        let repo = MockDataRepo::new()
+            .reply_for(1, Reply::ok(data(1)))
+            .reply_for(1, Reply::err(DataRepoError::Conflict))
+            .reply(Reply::ok(data(99)));
+
+        assert_eq!(repo.retrieve(1).await.unwrap().id, 1);
+        assert!(matches!(repo.retrieve(1).await, Err(DataRepoError::Conflict)));
+        // The last reply in a sequence repeats
+        assert!(matches!(repo.retrieve(1).await, Err(DataRepoError::Conflict)));
+
+        assert_eq!(repo.retrieve(2).await.unwrap().id, 99);
+        assert_eq!(repo.list(&ListQuery::default()).await.unwrap().items.len(), 1);
+
+        repo.assert_call_count(5);
+        repo.assert_called_with(2);
+        assert!(matches!(repo.calls()[4], DataRepoCall::List(_)));
+
+        assert!(matches!(MockDataRepo::new().retrieve(1).await, Err(DataRepoError::NotFound)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_delayed_replies() {
+        let repo = MockDataRepo::new().reply(Reply::ok(data(1)).after(Duration::from_secs(5)));
+
+        let started = tokio::time::Instant::now();
+        repo.retrieve(1).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_panicking_replies() {
+        let repo = MockDataRepo::new().reply_for(13, Reply::panic("unlucky"));
+        let client = TestApp::builder().with_data_repo(repo.clone()).build();
+
+        let res = client.get("/data/13").send().await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        repo.assert_called_with(13);
+    }
+}
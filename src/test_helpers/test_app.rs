@@ -0,0 +1,97 @@
+#![allow(dead_code)]
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::container::Container;
+use crate::data_repos::InMemoryDataRepo;
+use crate::scope::ScopedFactory;
+use crate::auth::{Authenticator, User};
+use crate::authz::{AllowAll, AuthorizationPolicy, DynAuthorizationPolicy};
+use crate::{build_app, AppState, DataRepo};
+
+use super::{FakeAuthenticator, TestClient};
+
+/// The production route table and middleware, served with the default services except for the
+/// ones a test overrides. Every caller is authorized unless a test sets a policy with
+/// [`TestAppBuilder::with_policy`].
+pub(crate) struct TestApp;
+
+impl TestApp {
+    pub(crate) fn builder() -> TestAppBuilder {
+        TestAppBuilder::default()
+    }
+}
+
+type Override = Box<dyn FnOnce(&mut Container)>;
+
+#[derive(Default)]
+pub(crate) struct TestAppBuilder {
+    config: Config,
+    data_repo: Option<Arc<dyn DataRepo + Send + Sync>>,
+    users: FakeAuthenticator,
+    policy: Option<DynAuthorizationPolicy>,
+    overrides: Vec<Override>,
+}
+
+impl TestAppBuilder {
+    /// Builds the app and starts serving it, panicking if an override left a required service
+    /// unregistered.
+    pub(crate) fn build(self) -> TestClient {
+        let data_repo = self.data_repo.unwrap_or_else(|| Arc::new(InMemoryDataRepo::default()));
+
+        let mut app_state = AppState::new(data_repo)
+            .with_authenticator(Arc::new(self.users) as Arc<dyn Authenticator + Send + Sync>)
+            .with_policy(self.policy.unwrap_or_else(|| Arc::new(AllowAll)));
+        let container = app_state.container_mut();
+        for apply in self.overrides {
+            apply(container);
+        }
+
+        TestClient::new(build_app(app_state, &self.config).expect("test app to register every required service"))
+    }
+
+    pub(crate) fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Replaces the in-memory repo. Unlike registering it with [`Self::with_service`], the repo is
+    /// wrapped with the same instrumentation as in production.
+    pub(crate) fn with_data_repo(mut self, data_repo: impl DataRepo + Send + Sync + 'static) -> Self {
+        self.data_repo = Some(Arc::new(data_repo));
+        self
+    }
+
+    /// Lets requests authenticate as `user` by sending its id as the bearer token, see
+    /// [`FakeAuthenticator`].
+    pub(crate) fn with_user(mut self, user: User) -> Self {
+        self.users = self.users.with_user(user);
+        self
+    }
+
+    pub(crate) fn with_policy(mut self, policy: impl AuthorizationPolicy + Send + Sync + 'static) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    pub(crate) fn with_scoped<T, F>(mut self, factory: F) -> Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+        F: ScopedFactory<T> + 'static,
+    {
+        self.overrides.push(Box::new(move |container| {
+            container.register_scoped::<T, F>(factory);
+        }));
+        self
+    }
+
+    pub(crate) fn with_service<T>(mut self, service: Arc<T>) -> Self
+    where
+        T: ?Sized + Send + Sync + 'static,
+    {
+        self.overrides.push(Box::new(move |container| {
+            container.register(service);
+        }));
+        self
+    }
+}
@@ -100,6 +100,12 @@ impl RequestBuilder {
         self
     }
 
+    /// Authenticates the request with `token`, which for a [`FakeAuthenticator`](super::FakeAuthenticator)
+    /// is just the id of the user to act as.
+    pub(crate) fn bearer(self, token: &str) -> Self {
+        self.header(http::header::AUTHORIZATION, format!("Bearer {token}"))
+    }
+
     pub(crate) fn json<T>(mut self, json: &T) -> Self
     where
         T: serde::Serialize,
@@ -0,0 +1,239 @@
+use axum::async_trait;
+use axum::body::HttpBody;
+use axum::extract::{FromRequest, Json};
+use axum::response::{IntoResponse, Response};
+use axum::BoxError;
+use http::{Request, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::problem::Problem;
+
+/// A single invalid field in a request body, addressed by its name (or `name[index]` for list
+/// elements).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Every invalid field found in a request body. Rendered as a 422 problem whose `errors`
+/// extension lists each [`FieldError`].
+#[derive(Debug, Default)]
+pub struct ValidationErrors(Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.0
+    }
+}
+
+impl IntoResponse for ValidationErrors {
+    fn into_response(self) -> Response {
+        Problem::new(StatusCode::UNPROCESSABLE_ENTITY)
+            .with_detail(format!("{} field(s) in the request body are invalid", self.0.len()))
+            .with_extension("errors", serde_json::json!(self.0))
+            .into_response()
+    }
+}
+
+/// Reads the fields of a JSON object one at a time, recording every problem it finds instead of
+/// stopping at the first the way deserializing straight into a struct would.
+pub struct Fields {
+    object: Map<String, Value>,
+    errors: ValidationErrors,
+}
+
+impl Fields {
+    fn new(value: Value) -> Result<Self, ValidationErrors> {
+        match value {
+            Value::Object(object) => Ok(Self {
+                object,
+                errors: ValidationErrors::default(),
+            }),
+            _ => {
+                let mut errors = ValidationErrors::default();
+                errors.add("", "expected a JSON object");
+                Err(errors)
+            }
+        }
+    }
+
+    pub fn required<T: DeserializeOwned>(&mut self, field: &str) -> Option<T> {
+        match self.object.remove(field) {
+            Some(value) => self.parse(field, value),
+            None => {
+                self.errors.add(field, "is required");
+                None
+            }
+        }
+    }
+
+    /// Like [`Fields::required`], falling back to the default value when the field is absent.
+    pub fn optional<T: DeserializeOwned + Default>(&mut self, field: &str) -> Option<T> {
+        match self.object.remove(field) {
+            Some(value) => self.parse(field, value),
+            None => Some(T::default()),
+        }
+    }
+
+    /// Like [`Fields::optional`], but tells an absent field apart from one that was sent, even
+    /// when it was sent as `null`.
+    pub fn present<T: DeserializeOwned>(&mut self, field: &str) -> Option<Option<T>> {
+        match self.object.remove(field) {
+            Some(value) => self.parse(field, value).map(Some),
+            None => Some(None),
+        }
+    }
+
+    /// Records `message` against `field` and discards the value if `rule` fails.
+    pub fn ensure<T>(&mut self, field: &str, value: T, rule: impl FnOnce(&T) -> Result<(), String>) -> Option<T> {
+        match rule(&value) {
+            Ok(()) => Some(value),
+            Err(message) => {
+                self.errors.add(field, message);
+                None
+            }
+        }
+    }
+
+    pub fn add_error(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.errors.add(field, message);
+    }
+
+    /// The number of errors recorded so far, so a caller can tell whether its own checks failed.
+    pub fn error_count(&self) -> usize {
+        self.errors.0.len()
+    }
+
+    fn parse<T: DeserializeOwned>(&mut self, field: &str, value: Value) -> Option<T> {
+        match serde_json::from_value(value) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.errors.add(field, err.to_string());
+                None
+            }
+        }
+    }
+
+    fn finish<T>(mut self, parsed: Option<T>) -> Result<T, ValidationErrors> {
+        let unknown: Vec<String> = self.object.keys().cloned().collect();
+        for field in unknown {
+            self.errors.add(field, "is not a known field");
+        }
+
+        match parsed {
+            Some(parsed) if self.errors.0.is_empty() => Ok(parsed),
+            // A rule that discarded its value without recording why would otherwise slip through
+            None if self.errors.0.is_empty() => {
+                self.errors.add("", "is invalid");
+                Err(self.errors)
+            }
+            _ => Err(self.errors),
+        }
+    }
+}
+
+/// A request body that can be checked field by field.
+pub trait Validate: Sized {
+    /// Reads `Self` out of `fields`, returning `None` if any field it needed was invalid. The
+    /// errors themselves are recorded in `fields`.
+    fn validate(fields: &mut Fields) -> Option<Self>;
+}
+
+/// Parses and validates a JSON body. Malformed JSON is rejected the same way as [`Json`], and a
+/// well formed body with invalid fields is rejected with [`ValidationErrors`].
+pub struct Valid<T>(pub T);
+
+#[async_trait]
+impl<S, B, T> FromRequest<S, B> for Valid<T>
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+    S: Send + Sync,
+    T: Validate,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<Value>::from_request(req, state).await.map_err(|rejection| {
+            Problem::new(rejection.status())
+                .with_detail(rejection.body_text())
+                .into_response()
+        })?;
+
+        let mut fields = Fields::new(value).map_err(IntoResponse::into_response)?;
+        let parsed = T::validate(&mut fields);
+
+        fields.finish(parsed).map(Valid).map_err(IntoResponse::into_response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    impl Validate for Point {
+        fn validate(fields: &mut Fields) -> Option<Self> {
+            let x = fields.required("x");
+            let y = fields
+                .optional("y")
+                .and_then(|y| fields.ensure("y", y, |y| if *y >= 0 { Ok(()) } else { Err("must not be negative".into()) }));
+
+            Some(Point { x: x?, y: y? })
+        }
+    }
+
+    fn validate(value: Value) -> Result<Point, ValidationErrors> {
+        let mut fields = Fields::new(value)?;
+        let parsed = Point::validate(&mut fields);
+        fields.finish(parsed)
+    }
+
+    fn invalid_fields(value: Value) -> Vec<String> {
+        match validate(value) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.errors().iter().map(|error| error.field.clone()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_valid_bodies() {
+        let point = validate(serde_json::json!({"x": 1, "y": 2})).unwrap();
+        assert_eq!((point.x, point.y), (1, 2));
+
+        let point = validate(serde_json::json!({"x": 1})).unwrap();
+        assert_eq!(point.y, 0);
+    }
+
+    #[test]
+    fn test_every_invalid_field_is_reported() {
+        assert_eq!(invalid_fields(serde_json::json!({"y": -1})), vec!["x", "y"]);
+        assert_eq!(invalid_fields(serde_json::json!({"x": "one", "z": 3})), vec!["x", "z"]);
+        assert_eq!(invalid_fields(serde_json::json!([1, 2])), vec![""]);
+    }
+
+    #[test]
+    fn test_present_tells_null_apart_from_absent() {
+        let mut fields = Fields::new(serde_json::json!({"a": null, "b": 1})).unwrap();
+
+        assert_eq!(fields.present::<Value>("a"), Some(Some(Value::Null)));
+        assert_eq!(fields.present::<Value>("c"), Some(None));
+        assert_eq!(fields.present::<String>("b"), None);
+        assert_eq!(fields.error_count(), 1);
+    }
+}
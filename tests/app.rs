@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::Router;
+use axum_testing::auth::{ApiKeyAuthenticator, User};
+use axum_testing::config::Config;
+use axum_testing::{build_app, build_data_repo, AppState};
+use http::{Request, StatusCode};
+use tower::ServiceExt;
+
+const API_KEY: &str = "test-key";
+
+fn authorized(builder: http::request::Builder) -> http::request::Builder {
+    builder.header("authorization", format!("Bearer {API_KEY}"))
+}
+
+async fn app() -> Router {
+    let config = Config::default();
+    let data_repo = build_data_repo(&config.repo).await.unwrap();
+
+    let api_keys = ApiKeyAuthenticator::default().with_key(API_KEY, User::new("tester").with_scopes(["data:admin"]));
+    let app_state = AppState::new(data_repo).with_authenticator(Arc::new(api_keys));
+
+    build_app(app_state, &config).unwrap()
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, serde_json::Value) {
+    let res = app.clone().oneshot(request).await.unwrap();
+    let status = res.status();
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    let json = if body.is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_slice(&body).unwrap()
+    };
+
+    (status, json)
+}
+
+#[tokio::test]
+async fn test_data_round_trip() {
+    let app = app().await;
+
+    let create = authorized(Request::post("/data"))
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"id": 5, "name": "five"}"#))
+        .unwrap();
+    let (status, body) = send(&app, create).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["id"], 5);
+
+    let (status, body) = send(&app, authorized(Request::get("/data/5")).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["id"], 5);
+
+    let (status, _) = send(&app, authorized(Request::delete("/data/5")).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, body) = send(&app, authorized(Request::get("/data/5")).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["status"], 404);
+}
+
+#[tokio::test]
+async fn test_data_routes_require_credentials() {
+    let app = app().await;
+
+    let (status, body) = send(&app, Request::get("/data").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["status"], 401);
+}
+
+#[tokio::test]
+async fn test_app_includes_the_middleware_stack() {
+    let app = app().await;
+
+    let res = app
+        .clone()
+        .oneshot(Request::get("/readyz").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().contains_key("x-request-id"));
+
+    let res = app
+        .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    assert!(String::from_utf8_lossy(&body).contains(r#"route="/readyz""#));
+}